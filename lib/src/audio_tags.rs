@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use id3::TagLike;
+use crate::template::{SourceFile, Token};
+use crate::tokens::{sanitize_value, NumberFormat, Resolved, TokenProvider};
+
+/// Tag fields that can be used in `{tag:...}` tokens, and whether they hold a number.
+const FIELDS: [(&str, bool); 9] = [
+    ("artist", false),
+    ("albumartist", false),
+    ("album", false),
+    ("title", false),
+    ("genre", false),
+    ("composer", false),
+    ("track", true),
+    ("disc", true),
+    ("year", true),
+];
+
+/// Upper bound for the size of a metadata block or packet, to protect against corrupt files.
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// The tags of one audio file, keyed by field name.
+type AudioTags = HashMap<String, String>;
+
+/// Audio Tag Provider
+///
+/// Resolves `{tag:field}` and `{tag:field:format}` tokens from the tags of audio files: ID3v2
+/// (MP3, AIFF, WAV), FLAC and Ogg Vorbis/Opus comments. Known fields are `artist`,
+/// `albumartist`, `album`, `title`, `genre`, `composer`, `track`, `disc` and `year`; the numeric
+/// ones accept a width such as `{tag:track:02}`.
+///
+/// Values are sanitized so they can be used as a path component. A file that lacks a requested
+/// tag is left where it is.
+///
+#[derive(Default)]
+pub struct AudioTagProvider {
+    cache: Mutex<HashMap<PathBuf, Arc<AudioTags>>>,
+}
+
+/// Split Tag Argument
+///
+/// Splits the argument of a `{tag:...}` token into the field, whether it is numeric, and its
+/// optional number format.
+///
+fn split_argument(token: &Token) -> Result<(&'static str, bool, Option<NumberFormat>), String> {
+    let argument = token.argument.as_deref()
+        .ok_or_else(|| "expected a tag field, e.g. {tag:artist}".to_string())?;
+    let (name, format) = match argument.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (argument, None),
+    };
+    let (name, numeric) = FIELDS.iter()
+        .find(|(known, _)| *known == name)
+        .ok_or_else(|| format!("unknown tag field '{}'", name))?;
+    match format {
+        Some(_) if !numeric => Err(format!("tag field '{}' is not a number and takes no format",
+                                           name)),
+        Some(format) => Ok((name, true, Some(NumberFormat::parse(format)?))),
+        None => Ok((name, *numeric, None)),
+    }
+}
+
+/// Field Name
+///
+/// Maps a Vorbis comment name to a tag field name.
+///
+fn vorbis_field(name: &str) -> Option<&'static str> {
+    match name.to_ascii_uppercase().as_str() {
+        "ARTIST" => Some("artist"),
+        "ALBUMARTIST" | "ALBUM ARTIST" => Some("albumartist"),
+        "ALBUM" => Some("album"),
+        "TITLE" => Some("title"),
+        "GENRE" => Some("genre"),
+        "COMPOSER" => Some("composer"),
+        "TRACKNUMBER" => Some("track"),
+        "DISCNUMBER" => Some("disc"),
+        "DATE" | "YEAR" => Some("year"),
+        _ => None,
+    }
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Parse Vorbis Comments
+///
+/// Parses a Vorbis comment block, as found in FLAC files and Ogg Vorbis/Opus headers.
+///
+fn parse_vorbis_comments(data: &[u8]) -> AudioTags {
+    let mut tags = AudioTags::new();
+    let Some(vendor_length) = read_u32_le(data, 0) else { return tags };
+    let mut offset = 4 + vendor_length as usize;
+    let Some(count) = read_u32_le(data, offset) else { return tags };
+    offset += 4;
+    for _ in 0..count {
+        let Some(length) = read_u32_le(data, offset) else { break };
+        let Some(comment) = data.get(offset + 4..offset + 4 + length as usize) else { break };
+        offset += 4 + length as usize;
+        let comment = String::from_utf8_lossy(comment);
+        if let Some((name, value)) = comment.split_once('=') {
+            if let Some(field) = vorbis_field(name) {
+                tags.entry(field.to_string()).or_insert_with(|| value.to_string());
+            }
+        }
+    }
+    tags
+}
+
+/// Read Flac Tags
+///
+/// Reads the Vorbis comment block of a FLAC file, whose `fLaC` signature has been consumed.
+///
+fn read_flac_tags(reader: &mut impl Read) -> std::io::Result<AudioTags> {
+    loop {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7f;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        if block_type == 4 {
+            let mut block = vec![0u8; length.min(MAX_BLOCK_SIZE)];
+            reader.read_exact(&mut block)?;
+            return Ok(parse_vorbis_comments(&block));
+        }
+        if is_last {
+            return Ok(AudioTags::new());
+        }
+        std::io::copy(&mut reader.take(length as u64), &mut std::io::sink())?;
+    }
+}
+
+/// Read Ogg Tags
+///
+/// Reads the comment header of an Ogg Vorbis or Opus stream, which is its second packet.
+///
+fn read_ogg_tags(reader: &mut impl Read) -> std::io::Result<AudioTags> {
+    let mut packets: Vec<Vec<u8>> = vec![Vec::new()];
+    while packets.len() < 3 {
+        let mut header = [0u8; 27];
+        if reader.read_exact(&mut header).is_err() || &header[0..4] != b"OggS" {
+            break;
+        }
+        let mut lacing = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut lacing)?;
+        for size in lacing {
+            let mut segment = vec![0u8; size as usize];
+            reader.read_exact(&mut segment)?;
+            let packet = packets.last_mut().expect("there is always a current packet");
+            if packet.len() + segment.len() > MAX_BLOCK_SIZE {
+                return Ok(AudioTags::new());
+            }
+            packet.extend_from_slice(&segment);
+            if size < 255 {
+                packets.push(Vec::new());
+            }
+        }
+    }
+    let comments = match packets.get(1) {
+        Some(packet) if packet.starts_with(b"\x03vorbis") => &packet[7..],
+        Some(packet) if packet.starts_with(b"OpusTags") => &packet[8..],
+        _ => return Ok(AudioTags::new()),
+    };
+    Ok(parse_vorbis_comments(comments))
+}
+
+/// Read Id3 Tags
+///
+/// Reads the ID3v2 tag of an MP3, AIFF or WAV file.
+///
+fn read_id3_tags(reader: impl Read + Seek) -> Result<AudioTags, String> {
+    let tag = match id3::Tag::read_from2(reader) {
+        Ok(tag) => tag,
+        Err(id3::Error { kind: id3::ErrorKind::NoTag, .. }) => return Ok(AudioTags::new()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut tags = AudioTags::new();
+    let mut insert = |field: &str, value: Option<String>| {
+        if let Some(value) = value {
+            tags.insert(field.to_string(), value);
+        }
+    };
+    insert("artist", tag.artist().map(str::to_string));
+    insert("albumartist", tag.album_artist().map(str::to_string));
+    insert("album", tag.album().map(str::to_string));
+    insert("title", tag.title().map(str::to_string));
+    insert("genre", tag.genre_parsed().map(|genre| genre.to_string()));
+    insert("composer", tag.get("TCOM").and_then(|frame| frame.content().text()).map(str::to_string));
+    insert("track", tag.track().map(|track| track.to_string()));
+    insert("disc", tag.disc().map(|disc| disc.to_string()));
+    insert("year", tag.year().or_else(|| tag.date_recorded().map(|date| date.year))
+        .map(|year| year.to_string()));
+    Ok(tags)
+}
+
+/// Read Audio Tags
+///
+/// Reads the tags of an audio file, detecting its format from the file signature.
+///
+fn read_audio_tags(source: &Path) -> Result<AudioTags, String> {
+    let mut reader = BufReader::new(File::open(source).map_err(|e| e.to_string())?);
+    let mut signature = [0u8; 4];
+    let signature_length = reader.read(&mut signature).map_err(|e| e.to_string())?;
+    match &signature[..signature_length] {
+        b"fLaC" => read_flac_tags(&mut reader).map_err(|e| e.to_string()),
+        b"OggS" => {
+            reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+            read_ogg_tags(&mut reader).map_err(|e| e.to_string())
+        }
+        _ => {
+            reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+            read_id3_tags(reader)
+        }
+    }
+}
+
+impl AudioTagProvider {
+    fn tags(&self, source: &Path) -> Result<Arc<AudioTags>, String> {
+        if let Some(tags) = self.cache.lock().expect("tag cache is poisoned").get(source) {
+            return Ok(Arc::clone(tags));
+        }
+        let tags = Arc::new(read_audio_tags(source)?);
+        self.cache.lock().expect("tag cache is poisoned")
+            .insert(source.to_path_buf(), Arc::clone(&tags));
+        Ok(tags)
+    }
+}
+
+impl TokenProvider for AudioTagProvider {
+    fn provides(&self, name: &str) -> bool {
+        name == "tag"
+    }
+
+    fn validate(&self, token: &Token) -> Result<(), String> {
+        split_argument(token).map(|_| ())
+    }
+
+    fn resolve(&self, token: &Token, file: &SourceFile) -> Result<Resolved, String> {
+        let source = file.path;
+        let (name, numeric, format) = split_argument(token)?;
+        let tags = self.tags(source)?;
+        let value = match tags.get(name).map(|value| value.trim()).filter(|value| !value.is_empty()) {
+            Some(value) => value,
+            None => return Ok(Resolved::Skip(format!("no {} tag", name))),
+        };
+        if !numeric {
+            return Ok(Resolved::Value(sanitize_value(value)));
+        }
+        // "3/12" for track 3 of 12, "2023-11-07" for a date
+        let number = value.split(['/', '-']).next().unwrap_or(value).trim();
+        match (number.parse::<i64>(), format) {
+            (Ok(number), Some(format)) => Ok(Resolved::Value(format.format(number))),
+            (Ok(number), None) => Ok(Resolved::Value(number.to_string())),
+            (Err(_), _) => Err(format!("{} tag '{}' is not a number", name, value)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+fn vorbis_comment_block(comments: &[&str]) -> Vec<u8> {
+    let mut block = Vec::new();
+    block.extend_from_slice(&4u32.to_le_bytes());
+    block.extend_from_slice(b"test");
+    block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        block.extend_from_slice(comment.as_bytes());
+    }
+    block
+}
+
+#[test]
+fn test_validate_tag_tokens() {
+    let token = |argument: Option<&str>| Token::new("tag", argument);
+    let provider = AudioTagProvider::default();
+    assert!(provider.validate(&token(Some("artist"))).is_ok());
+    assert!(provider.validate(&token(Some("track:02"))).is_ok());
+    assert!(provider.validate(&token(None)).is_err());
+    assert!(provider.validate(&token(Some("artst"))).is_err());
+    assert!(provider.validate(&token(Some("title:02"))).is_err());
+    assert!(provider.validate(&token(Some("track:x"))).is_err());
+}
+
+#[test]
+fn test_resolve_flac_tags() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("song.flac");
+    let comments = vorbis_comment_block(&["ARTIST=AC/DC", "title=Thunderstruck", "TRACKNUMBER=3/12"]);
+    let mut data = b"fLaC".to_vec();
+    // STREAMINFO block, then the last block with the comments
+    data.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+    data.extend_from_slice(&[0u8; 0x22]);
+    data.push(0x84);
+    data.extend_from_slice(&(comments.len() as u32).to_be_bytes()[1..]);
+    data.extend_from_slice(&comments);
+    std::fs::write(&path, data).expect("Failed to write file");
+    let file = SourceFile::new(&path);
+    let token = |argument: &str| Token::new("tag", Some(argument));
+
+    let provider = AudioTagProvider::default();
+    assert_eq!(provider.resolve(&token("artist"), &file).unwrap(),
+               Resolved::Value("AC_DC".to_string()));
+    assert_eq!(provider.resolve(&token("title"), &file).unwrap(),
+               Resolved::Value("Thunderstruck".to_string()));
+    assert_eq!(provider.resolve(&token("track:02"), &file).unwrap(),
+               Resolved::Value("03".to_string()));
+    assert_eq!(provider.resolve(&token("album"), &file).unwrap(),
+               Resolved::Skip("no album tag".to_string()));
+}
+
+#[test]
+fn test_resolve_ogg_tags() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("song.ogg");
+    let mut comment_packet = b"\x03vorbis".to_vec();
+    comment_packet.extend_from_slice(&vorbis_comment_block(&["ALBUM=Live", "DATE=1991-09-10"]));
+    let mut data = Vec::new();
+    for (sequence, packet) in [b"\x01vorbis".to_vec(), comment_packet].iter().enumerate() {
+        data.extend_from_slice(b"OggS");
+        data.extend_from_slice(&[0u8; 14]);
+        data.extend_from_slice(&(sequence as u32).to_le_bytes());
+        data.extend_from_slice(&[0u8; 4]);
+        data.push(1);
+        data.push(packet.len() as u8);
+        data.extend_from_slice(packet);
+    }
+    std::fs::write(&path, data).expect("Failed to write file");
+    let file = SourceFile::new(&path);
+    let token = |argument: &str| Token::new("tag", Some(argument));
+
+    let provider = AudioTagProvider::default();
+    assert_eq!(provider.resolve(&token("album"), &file).unwrap(),
+               Resolved::Value("Live".to_string()));
+    assert_eq!(provider.resolve(&token("year"), &file).unwrap(),
+               Resolved::Value("1991".to_string()));
+}
+
+#[test]
+fn test_resolve_id3_tags() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("song.mp3");
+    std::fs::write(&path, [0xffu8, 0xfb, 0x90, 0x00]).expect("Failed to write file");
+    let mut tag = id3::Tag::new();
+    tag.set_artist("Artist");
+    tag.set_album("Album: Part 1/2");
+    tag.set_track(7);
+    tag.write_to_path(&path, id3::Version::Id3v24).expect("Failed to write tag");
+    let file = SourceFile::new(&path);
+    let token = |argument: &str| Token::new("tag", Some(argument));
+
+    let provider = AudioTagProvider::default();
+    assert_eq!(provider.resolve(&token("album"), &file).unwrap(),
+               Resolved::Value("Album: Part 1_2".to_string()));
+    assert_eq!(provider.resolve(&token("track:03"), &file).unwrap(),
+               Resolved::Value("007".to_string()));
+    assert_eq!(provider.resolve(&token("title"), &file).unwrap(),
+               Resolved::Skip("no title tag".to_string()));
+}
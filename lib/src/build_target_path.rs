@@ -1,15 +1,27 @@
+use std::collections::BTreeMap;
 use regex::Regex;
 use crate::search_by_pattern;
-use search_by_pattern::parse_full_path;
+use search_by_pattern::{parse_full_path, split_capture_names};
+use crate::template::{SourceFile, Template, COUNTER};
+use crate::tokens::{Resolved, Tokens};
+
+/// The parts of a filename matched by the `*` wildcards of a source pattern.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenericParts {
+    /// Every matched part, in the order of the wildcards.
+    pub positional: Vec<String>,
+    /// The parts matched by named wildcards such as `*{year}`, by name.
+    pub named: BTreeMap<String, String>,
+}
 
 /// Extract Generic Parts
 ///
 /// Extracts generic parts from a filename which are hidden under `*` based on
-/// a file pattern and returns them as a vector of strings.
+/// a file pattern.
 ///
 /// This function takes two full paths as input: one containing the full filename and another containing
 /// a file pattern with placeholders. It extracts the parts of the filename that match the pattern and
-/// returns them as a vector of strings.
+/// returns them both in the order of the wildcards and, for named wildcards such as `*{year}`, by name.
 ///
 /// # Arguments
 ///
@@ -18,107 +30,231 @@ use search_by_pattern::parse_full_path;
 ///
 /// # Returns
 ///
-/// A vector of strings representing the extracted generic parts.
+/// The extracted generic parts, or an error if the pattern cannot be turned into a regex.
 ///
 /// # Example
 ///
 /// ```no
 /// let full_path_with_filename = "path/to/some_A_filename.bin";
-/// let full_path_with_file_pattern = "path/to/some_*_filename.*";
-/// let generic_parts = extract_generic_parts(full_path_with_filename, full_path_with_file_pattern);
+/// let full_path_with_file_pattern = "path/to/some_*{letter}_filename.*";
+/// let generic_parts = extract_generic_parts(full_path_with_filename, full_path_with_file_pattern)?;
+/// assert_eq!(generic_parts.positional, vec!["A", "bin"]);
+/// assert_eq!(generic_parts.named["letter"], "A");
 /// ```
 ///
 /// This will extract the generic parts from the filename based on the file pattern.
 ///
 pub fn extract_generic_parts(full_path_with_filename: &str,
-                             full_path_with_file_pattern: &str) -> Vec<String> {
+                             full_path_with_file_pattern: &str) -> Result<GenericParts, String> {
     let (_, filename) = parse_full_path(full_path_with_filename);
     let (_, file_pattern) = parse_full_path(full_path_with_file_pattern);
-    let regex_file_pattern = format!("^{}$",
-                                     file_pattern.replace(".", r"\.").replace("*", "(.*?)"));
-    let regex = Regex::new(&regex_file_pattern).unwrap();
+    let (file_pattern, names) = split_capture_names(file_pattern);
+    // Everything but the wildcards is matched literally, brackets and dollars included
+    let literals: Vec<String> = file_pattern.split('*').map(regex::escape).collect();
+    let regex_file_pattern = format!("(?s)^{}$", literals.join("(.*?)"));
+    let regex = Regex::new(&regex_file_pattern)
+        .map_err(|e| format!("mmv: Invalid source pattern '{}': {}", full_path_with_file_pattern, e))?;
 
+    let mut generic_parts = GenericParts::default();
     if let Some(captures) = regex.captures(filename) {
-        return captures
+        generic_parts.positional = captures
             .iter()
             .skip(1)
             .filter_map(|capture| capture.map(|c|
                 c.as_str().to_string()))
             .collect();
+        generic_parts.named = names.into_iter()
+            .zip(&generic_parts.positional)
+            .filter_map(|(name, part)| name.map(|name| (name, part.clone())))
+            .collect();
     }
-    Vec::new()
+    Ok(generic_parts)
 }
 
-/// Build Target Path
-///
-/// Builds a target path by inserting extracted parts into a given output path pattern.
+/// Capture Names
 ///
-/// This function takes a vector of extracted parts and an output path pattern with placeholders (#1, #2, etc.),
-/// and constructs the target path by replacing the placeholders with the extracted parts.
+/// Lists the captures a source pattern produces, i.e. the `*` wildcards in its filename, with
+/// the names given to them as in `*{year}`.
 ///
 /// # Arguments
 ///
-/// * `substr_to_insert` - A vector of extracted parts.
-/// * `full_output_path_pattern` - The full output path pattern with placeholders.
+/// * `full_path_with_file_pattern` - The full path containing the file pattern.
 ///
 /// # Returns
 ///
-/// The constructed target path as a `String`.
+/// The name of every capture, `None` for unnamed ones, or an error if a name is used twice or
+/// is reserved for the file counter `#{n}`.
+///
+pub fn capture_names(full_path_with_file_pattern: &str) -> Result<Vec<Option<String>>, String> {
+    let (_, file_pattern) = parse_full_path(full_path_with_file_pattern);
+    let (_, names) = split_capture_names(file_pattern);
+    for (index, name) in names.iter().enumerate() {
+        if let Some(name) = name {
+            if name == COUNTER {
+                return Err(format!("mmv: Capture name '{}' is reserved for the file counter #{{{}}}",
+                                   name, COUNTER));
+            }
+            if names[..index].contains(&Some(name.clone())) {
+                return Err(format!("mmv: Capture name '{}' is used more than once in '{}'",
+                                   name, full_path_with_file_pattern));
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Build Target Path
+///
+/// Builds a target path by inserting extracted parts into a given output path template.
 ///
-/// # Panics
+/// This function takes a source file with the parts extracted from its name and a template parsed
+/// from an output path pattern with placeholders (#1, #2, etc.), and constructs the target path by
+/// replacing the placeholders with the extracted parts.
+///
+/// Besides plain `#N` markers, `#{N}` refers to a capture unambiguously, e.g. `#{1}2`, and
+/// `#{name}` to a named wildcard such as `*{name}`. The braced form `#{N:-default}` substitutes `default` when the
+/// capture is empty, and `#{N:+segment}` emits `segment` only when the capture is non-empty,
+/// e.g. `#1#{2:+_#2}.txt` avoids a stray `_` when the second capture is empty. Tokens such as
+/// `{mtime:%Y}` or `{parent}` are resolved for the source file by the given providers.
+///
+/// # Arguments
 ///
-/// This function will panic if it encounters an invalid index in the output path pattern.
+/// * `source_file` - The matched source file and the parts extracted from its name.
+/// * `template` - The output path template, validated against the number of extracted parts.
+/// * `tokens` - The token providers the template was validated with.
+///
+/// # Returns
+///
+/// The constructed target path, `Resolved::Skip` if the file should be left where it is because
+/// a token has no value for it, or an error if a token cannot be resolved.
 ///
 /// # Example
 ///
 /// ```no
-/// let substr_to_insert = vec!["A".to_string(), "filename".to_string()];
-/// let full_output_path_pattern = "path2/to/changed_#1_filename.#2";
-/// let target_path = build_target_path(substr_to_insert, full_output_path_pattern);
+/// let substr_to_insert = extract_generic_parts("path/to/some_A_filename.bin",
+///                                               "path/to/some_*_*.bin")?;
+/// let source_file = SourceFile {
+///     path: Path::new("path/to/some_A_filename.bin"),
+///     captures: &substr_to_insert,
+///     root: Path::new("path/to"),
+///     counter: 1,
+/// };
+/// let tokens = Tokens::default();
+/// let template = Template::parse("path2/to/changed_#1_filename.#2")?;
+/// template.validate(&capture_names("path/to/some_*_*.bin")?, &tokens)?;
+/// let target_path = build_target_path(&source_file, &template, &tokens);
 /// ```
 ///
 /// This will build the target path by inserting the extracted parts into the output path pattern.
 ///
-pub fn build_target_path(substr_to_insert: Vec<String>, full_output_path_pattern: &str) -> String {
-    let (output_path, pattern) = parse_full_path(full_output_path_pattern);
-    let regex = Regex::new(r"#(\d+)").unwrap();
-    let filename_with_substr = regex.replace_all(pattern, |caps: &regex::Captures| {
-        let index: usize = caps[1].parse().expect("Invalid index");
-        if index <= substr_to_insert.len() {
-            substr_to_insert[index - 1].as_str()
-        } else {
-            // If the index is out of range, replace with an empty string
-            ""
-        }
-    });
-    let full_path = format!("{}/{}", output_path, filename_with_substr);
-    full_path.to_string()
+pub fn build_target_path(source_file: &SourceFile, template: &Template,
+                         tokens: &Tokens) -> Result<Resolved, String> {
+    template.render(source_file, tokens)
 }
 
 #[test]
 fn test_extract_generic_parts() {
-    assert_eq!(extract_generic_parts("some_file_name", "som*e_n*"),
+    assert_eq!(extract_generic_parts("some_file_name", "som*e_n*").unwrap().positional,
                vec!["e_fil", "ame"]);
-    assert_eq!(extract_generic_parts("a_bc_def_hello.txt", "*e*he*"),
+    assert_eq!(extract_generic_parts("a_bc_def_hello.txt", "*e*he*").unwrap().positional,
                vec!["a_bc_d", "f_", "llo.txt"]);
-    assert_eq!(extract_generic_parts("a_b", "a_*b"),
+    assert_eq!(extract_generic_parts("a_b", "a_*b").unwrap().positional,
                vec![""]);
-    assert_eq!(extract_generic_parts("a_b", "*a_*b"),
+    assert_eq!(extract_generic_parts("a_b", "*a_*b").unwrap().positional,
                vec!["", ""]);
+    assert_eq!(extract_generic_parts("a (1) [x]+$.txt", "a (*) [*]+$.*").unwrap().positional,
+               vec!["1", "x", "txt"]);
+    assert_eq!(extract_generic_parts("x{1}^|.txt", "x{*}^|.txt").unwrap().positional, vec!["1"]);
+    assert_eq!(extract_generic_parts("a.b", "a(.*").unwrap().positional, Vec::<String>::new());
+}
+
+#[test]
+fn test_extract_named_generic_parts() {
+    let generic_parts = extract_generic_parts("data/2023-11.csv", "data/*{year}-*{month}.*").unwrap();
+    assert_eq!(generic_parts.positional, vec!["2023", "11", "csv"]);
+    assert_eq!(generic_parts.named, BTreeMap::from([("year".to_string(), "2023".to_string()),
+                                                    ("month".to_string(), "11".to_string())]));
+}
+
+
+#[cfg(test)]
+fn source_file(generic_parts: &GenericParts) -> SourceFile<'_> {
+    SourceFile {
+        path: std::path::Path::new("file"),
+        captures: generic_parts,
+        root: std::path::Path::new(""),
+        counter: 1,
+    }
+}
+
+#[cfg(test)]
+fn parts(positional: &[&str]) -> GenericParts {
+    GenericParts {
+        positional: positional.iter().map(|part| part.to_string()).collect(),
+        named: BTreeMap::new(),
+    }
+}
+
+#[test]
+fn test_capture_names() {
+    assert_eq!(capture_names("path/to/some_*_filename.*").unwrap(), vec![None, None]);
+    assert_eq!(capture_names("path/*/file.txt").unwrap(), vec![]);
+    assert_eq!(capture_names("*{year}-*").unwrap(), vec![Some("year".to_string()), None]);
+    assert_eq!(capture_names("*{x}-*{x}").unwrap_err(),
+               "mmv: Capture name 'x' is used more than once in '*{x}-*{x}'");
+    assert!(capture_names("*{n}").is_err());
 }
 
 
 #[test]
 fn test_build_path_target() {
-    let generic_parts: Vec<String> = vec![String::from("hello"), String::from("world"),
-                                          String::from("txt")];
-    let path = "path/to/#1_#2.#3";
-    assert_eq!(build_target_path(generic_parts, path),
-               "path/to/hello_world.txt");
-
-    let generic_parts: Vec<String> = vec![String::from(""), String::from("he"),
-                                          String::from("j")];
-    let path = "path/to/#1#2#2#2_#3_#4.txt";
-    assert_eq!(build_target_path(generic_parts, path),
-               "path/to/hehehe_j_.txt");
+    let tokens = Tokens::empty();
+    let generic_parts = parts(&["hello", "world", "txt"]);
+    let template = Template::parse("path/to/#1_#2.#3").unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("path/to/hello_world.txt".to_string()));
+
+    let generic_parts = parts(&["", "he", "j"]);
+    let template = Template::parse("path/to/#1#2#2#2_#3_#4.txt").unwrap();
+    // The baseline rendered a marker past the last capture as empty, `path/to/hehehe_j_.txt`
+    assert!(template.validate(&[None, None, None], &tokens).unwrap_err()
+        .starts_with("mmv: Invalid destination pattern: there is no capture #4, the source pattern has 3 wildcard(s)"));
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap_err(),
+               "mmv: There is no capture #4 for file");
+    let template = Template::parse("path/to/#1#2#2#2_#3.txt").unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("path/to/hehehe_j.txt".to_string()));
+}
+
+
+#[test]
+fn test_build_path_target_default_and_conditional() {
+    let tokens = Tokens::empty();
+    let generic_parts = parts(&["report", ""]);
+    let template = Template::parse("path/to/#1#{2:+_#2}.txt").unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("path/to/report.txt".to_string()));
+    let template = Template::parse("path/to/#{2:-untitled}.txt").unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("path/to/untitled.txt".to_string()));
+    let template = Template::parse("path/to/#{1:+[#{1}]}#{2:+never}").unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("path/to/[report]".to_string()));
+
+    let generic_parts = parts(&["a", "b"]);
+    let template = Template::parse("path/to/#1#{2:+_#2}.txt").unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("path/to/a_b.txt".to_string()));
+}
+
+
+#[test]
+fn test_build_path_target_braced_and_named() {
+    let tokens = Tokens::empty();
+    let mut generic_parts = parts(&["2023", "11"]);
+    generic_parts.named.insert("year".to_string(), "2023".to_string());
+    let template = Template::parse("#{year}/#{2}1_#{year:+y}.csv").unwrap();
+    template.validate(&[Some("year".to_string()), None], &tokens).unwrap();
+    assert_eq!(build_target_path(&source_file(&generic_parts), &template, &tokens).unwrap(),
+               Resolved::Value("2023/111_y.csv".to_string()));
 }
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sha2::Digest;
+use crate::template::{SourceFile, Token};
+use crate::tokens::{Resolved, TokenProvider};
+
+/// Size of the buffer files are streamed through while hashing.
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Hash algorithms that can be used as tokens, and the length of their hex digest.
+const ALGORITHMS: [(&str, usize); 4] = [
+    ("sha256", 64),
+    ("sha1", 40),
+    ("md5", 32),
+    ("blake3", 64),
+];
+
+/// Content Hash Provider
+///
+/// Resolves `{sha256}`, `{sha1}`, `{md5}` and `{blake3}` tokens to the lowercase hex digest of the
+/// file contents. The digest can be truncated to its first characters, e.g. `{sha256:12}`.
+///
+/// Files are streamed rather than read into memory, and each digest is computed once per file
+/// even if the template uses it several times.
+///
+#[derive(Default)]
+pub struct ContentHashProvider {
+    cache: Mutex<HashMap<(PathBuf, String), String>>,
+}
+
+/// Stream Digest
+///
+/// Feeds the contents of a reader into a digest in fixed-size chunks.
+///
+fn stream_digest<D: Digest>(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Hash File
+///
+/// Computes the hex digest of a file's contents.
+///
+/// # Arguments
+///
+/// * `path` - The file to hash.
+/// * `algorithm` - One of `sha256`, `sha1`, `md5` or `blake3`.
+///
+/// # Returns
+///
+/// The lowercase hex digest, or an I/O error.
+///
+pub fn hash_file(path: &Path, algorithm: &str) -> io::Result<String> {
+    let file = File::open(path)?;
+    match algorithm {
+        "sha256" => stream_digest::<sha2::Sha256>(file),
+        "sha1" => stream_digest::<sha1::Sha1>(file),
+        "md5" => stream_digest::<md5::Md5>(file),
+        "blake3" => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut io::BufReader::with_capacity(BUFFER_SIZE, file), &mut hasher)?;
+            Ok(hasher.finalize().to_hex().to_string())
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                format!("unknown hash algorithm '{}'", algorithm))),
+    }
+}
+
+/// Digest Length
+///
+/// Parses the optional truncation of a hash token.
+///
+fn digest_length(token: &Token) -> Result<Option<usize>, String> {
+    let (_, full_length) = ALGORITHMS.iter()
+        .find(|(name, _)| *name == token.name)
+        .ok_or_else(|| format!("unknown hash algorithm '{}'", token.name))?;
+    match token.argument.as_deref() {
+        None => Ok(None),
+        Some(argument) => match argument.parse::<usize>() {
+            Ok(length) if length >= 1 && length <= *full_length => Ok(Some(length)),
+            _ => Err(format!("invalid digest length '{}', expected a number from 1 to {}",
+                             argument, full_length)),
+        },
+    }
+}
+
+impl TokenProvider for ContentHashProvider {
+    fn provides(&self, name: &str) -> bool {
+        ALGORITHMS.iter().any(|(algorithm, _)| *algorithm == name)
+    }
+
+    fn validate(&self, token: &Token) -> Result<(), String> {
+        digest_length(token).map(|_| ())
+    }
+
+    fn resolve(&self, token: &Token, file: &SourceFile) -> Result<Resolved, String> {
+        let source = file.path;
+        let length = digest_length(token)?;
+        let key = (source.to_path_buf(), token.name.clone());
+        let cached = self.cache.lock().expect("hash cache is poisoned").get(&key).cloned();
+        let digest = match cached {
+            Some(digest) => digest,
+            None => {
+                let digest = hash_file(source, &token.name).map_err(|e| e.to_string())?;
+                self.cache.lock().expect("hash cache is poisoned").insert(key, digest.clone());
+                digest
+            }
+        };
+        Ok(Resolved::Value(match length {
+            Some(length) => digest[..length].to_string(),
+            None => digest,
+        }))
+    }
+}
+
+
+#[test]
+fn test_hash_file() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("blob");
+    std::fs::write(&path, "hello").expect("Failed to write file");
+    assert_eq!(hash_file(&path, "sha256").unwrap(),
+               "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+    assert_eq!(hash_file(&path, "sha1").unwrap(), "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d");
+    assert_eq!(hash_file(&path, "md5").unwrap(), "5d41402abc4b2a76b9719d911017c592");
+    assert_eq!(hash_file(&path, "blake3").unwrap(),
+               "ea8f163db38682925e4491c5e58d4bb3506ef8c14eb78a86e908c5624a67200f");
+    assert!(hash_file(&path, "crc32").is_err());
+}
+
+#[test]
+fn test_resolve_hash_tokens() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("blob");
+    std::fs::write(&path, "hello").expect("Failed to write file");
+    let file = SourceFile::new(&path);
+    let provider = ContentHashProvider::default();
+    assert!(provider.validate(&Token::new("sha256", Some("12"))).is_ok());
+    assert!(provider.validate(&Token::new("md5", Some("33"))).is_err());
+    assert!(provider.validate(&Token::new("sha1", Some("0"))).is_err());
+    assert_eq!(provider.resolve(&Token::new("sha256", Some("12")), &file).unwrap(),
+               Resolved::Value("2cf24dba5fb0".to_string()));
+    assert_eq!(provider.resolve(&Token::new("md5", None), &file).unwrap(),
+               Resolved::Value("5d41402abc4b2a76b9719d911017c592".to_string()));
+}
@@ -0,0 +1,184 @@
+use crate::mass_move::MassMove;
+use crate::move_plan::{self, MovePlan, PlanEntry};
+use crate::tokens::Tokens;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Curation
+///
+/// A rename batch being previewed and curated, as in `mmv-tui`: the job is planned again every
+/// time its patterns change, and single entries can be left out of the plan. The sources left
+/// out are remembered across pattern changes, and the conflicts are computed over the entries
+/// kept, so leaving out one of two files with the same destination resolves the conflict.
+///
+/// The tokens of the job are built once, so its maps are read once and hashes and tags are
+/// cached across pattern changes.
+///
+/// # Example
+///
+/// ```no
+/// let mut curation = Curation::new(MassMove::new("photos/*.jpg", "sorted/#1.jpg"))?;
+/// curation.set_patterns("photos/*.jpg", "sorted/{mtime:%Y}/#1.jpg");
+/// curation.toggle("photos/private.jpg");
+/// execute(curation.selected(), &mut PrintReporter::default())?;
+/// ```
+///
+pub struct Curation {
+    job: MassMove,
+    tokens: Tokens,
+    planned: Result<MovePlan, String>,
+    selected: MovePlan,
+    excluded: HashSet<String>,
+}
+
+impl Curation {
+    /// Plans the job for the first time, or returns an error if its tokens cannot be built,
+    /// e.g. because a map file cannot be read.
+    pub fn new(job: MassMove) -> Result<Curation, String> {
+        let tokens = job.tokens()?;
+        let mut curation = Curation {
+            job,
+            tokens,
+            planned: Ok(MovePlan::default()),
+            selected: MovePlan::default(),
+            excluded: HashSet::new(),
+        };
+        curation.replan();
+        Ok(curation)
+    }
+
+    pub fn job(&self) -> &MassMove {
+        &self.job
+    }
+
+    /// Plans the job again with new patterns, unless they are the same.
+    pub fn set_patterns(&mut self, source_pattern: &str, destination_pattern: &str) {
+        if self.job.source_pattern() == source_pattern && self.job.destination_pattern() == destination_pattern {
+            return;
+        }
+        self.job = self.job.clone().patterns(source_pattern, destination_pattern);
+        self.replan();
+    }
+
+    /// The error of planning the job with the current patterns, e.g. an invalid pattern.
+    pub fn error(&self) -> Option<&str> {
+        self.planned.as_ref().err().map(|e| e.as_str())
+    }
+
+    /// Every planned entry, including the ones left out.
+    pub fn entries(&self) -> &[PlanEntry] {
+        match &self.planned {
+            Ok(plan) => &plan.entries,
+            Err(_) => &[],
+        }
+    }
+
+    /// Leaves the file out of the plan, or puts it back in.
+    pub fn toggle(&mut self, source: &str) {
+        if !self.excluded.remove(source) {
+            self.excluded.insert(source.to_string());
+        }
+        self.select();
+    }
+
+    pub fn is_included(&self, source: &str) -> bool {
+        !self.excluded.contains(source)
+    }
+
+    /// The conflicts of the kept entries that involve the file.
+    pub fn problems(&self, source: &str) -> Vec<String> {
+        self.selected.conflicts.iter()
+            .filter(|conflict| conflict.involves(source))
+            .map(|conflict| conflict.to_string())
+            .collect()
+    }
+
+    /// The plan of the kept entries, to pass to `move_plan::execute`.
+    pub fn selected(&self) -> &MovePlan {
+        &self.selected
+    }
+
+    fn replan(&mut self) {
+        self.planned = self.job.options().and_then(|options| {
+            move_plan::plan(self.job.source_pattern(), self.job.destination_pattern(), &options, &self.tokens)
+        });
+        self.select();
+    }
+
+    fn select(&mut self) {
+        let mut selected = self.planned.clone().unwrap_or_default();
+        selected.retain(|entry| !self.excluded.contains(&entry.source));
+        self.selected = selected;
+    }
+}
+
+/// Changed Range
+///
+/// Finds the part of a path that a rename changes, between the longest common prefix and the
+/// longest common suffix of the old and the new path, so that it can be highlighted.
+///
+/// # Returns
+///
+/// The byte ranges of the changed part in `old` and in `new`, on character boundaries; both are
+/// empty if the paths are the same.
+///
+/// # Example
+///
+/// ```no
+/// assert_eq!(changed_range("IMG_0001.jpg", "beach_0001.jpg"), (0..3, 0..5));
+/// ```
+///
+pub fn changed_range(old: &str, new: &str) -> (Range<usize>, Range<usize>) {
+    let prefix: usize = old.chars().zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..].chars().rev().zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    (prefix..old.len() - suffix, prefix..new.len() - suffix)
+}
+
+
+#[test]
+fn test_changed_range() {
+    assert_eq!(changed_range("IMG_0001.jpg", "beach_0001.jpg"), (0..3, 0..5));
+    assert_eq!(changed_range("a/report.txt", "a/report.md"), (9..12, 9..11));
+    assert_eq!(changed_range("фото.jpg", "фото.jpg"), (12..12, 12..12));
+    assert_eq!(changed_range("aa", "aaa"), (2..2, 2..3));
+    assert_eq!(changed_range("отчёт.txt", "отчет.txt"), (6..8, 6..8));
+}
+
+#[test]
+fn test_curation() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    for name in ["a.txt", "b.txt"] {
+        std::fs::write(path(name), name).expect("Failed to write file");
+    }
+    let mut curation = Curation::new(MassMove::new(&path("*.txt"), &path("#1.md"))).unwrap();
+    assert_eq!(curation.entries().len(), 2);
+    assert_eq!(curation.selected().entries.len(), 2);
+
+    curation.set_patterns(&path("*.txt"), &path("all.md"));
+    assert_eq!(curation.problems(&path("a.txt")).len(), 1);
+    assert!(!curation.selected().conflicts.is_empty());
+
+    curation.toggle(&path("b.txt"));
+    assert!(!curation.is_included(&path("b.txt")));
+    assert_eq!(curation.entries().len(), 2);
+    assert!(curation.selected().conflicts.is_empty());
+    assert_eq!(curation.selected().entries[0].source, path("a.txt"));
+
+    curation.set_patterns(&path("*.txt"), &path("#1.md"));
+    assert_eq!(curation.selected().entries.len(), 1);
+    curation.toggle(&path("b.txt"));
+    assert_eq!(curation.selected().entries.len(), 2);
+
+    curation.set_patterns(&path("*.txt"), &path("#9.md"));
+    assert!(curation.error().is_some());
+    assert!(curation.entries().is_empty());
+
+    assert!(Curation::new(MassMove::new(&path("*.txt"), &path("#1.md")).map(&path("missing.csv"))).is_err());
+}
@@ -0,0 +1,72 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use crate::file_metadata::{format_naive_date, validate_date_format, validate_naive_date_format};
+use crate::template::Filter;
+use crate::tokens::{FilterProvider, Resolved};
+
+/// Format of reformatted dates without an explicit output format.
+const DEFAULT_OUTPUT_FORMAT: &str = "%Y-%m-%d";
+
+/// Date Filter
+///
+/// Parses a marker value as a date with an input format and reformats it with an output format,
+/// both strftime-style: `#{1|date:%d-%m-%Y:%Y-%m-%d}` turns `07-11-2023` into `2023-11-07`.
+/// The output format defaults to `%Y-%m-%d` and may itself contain `:`, the input format may not.
+///
+pub struct DateFilter;
+
+/// Split Date Formats
+///
+/// Splits the argument of a `date` filter into the input and the output format.
+///
+fn split_formats(filter: &Filter) -> Result<(&str, &str), String> {
+    let argument = filter.argument.as_deref()
+        .filter(|argument| !argument.is_empty())
+        .ok_or_else(|| "expected an input date format, e.g. |date:%d-%m-%Y:%Y-%m-%d".to_string())?;
+    Ok(argument.split_once(':').unwrap_or((argument, DEFAULT_OUTPUT_FORMAT)))
+}
+
+impl FilterProvider for DateFilter {
+    fn provides(&self, name: &str) -> bool {
+        name == "date"
+    }
+
+    fn validate(&self, filter: &Filter) -> Result<(), String> {
+        let (input, output) = split_formats(filter)?;
+        validate_date_format(input)?;
+        validate_naive_date_format(output)
+    }
+
+    fn apply(&self, filter: &Filter, value: &str) -> Result<Resolved, String> {
+        let (input, output) = split_formats(filter)?;
+        // A format without a time of day parses as a date only
+        let date = NaiveDateTime::parse_from_str(value, input)
+            .or_else(|_| NaiveDate::parse_from_str(value, input)
+                .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight is a valid time")))
+            .map_err(|_| format!("'{}' is not a date in the format '{}'", value, input))?;
+        format_naive_date(&date, output).map(Resolved::Value)
+    }
+}
+
+
+#[test]
+fn test_date_filter() {
+    let filter = |argument: Option<&str>| Filter::new("date", argument);
+    assert!(DateFilter.validate(&filter(Some("%d-%m-%Y:%Y-%m-%d"))).is_ok());
+    assert!(DateFilter.validate(&filter(Some("%d-%m-%Y"))).is_ok());
+    assert!(DateFilter.validate(&filter(None)).is_err());
+    assert!(DateFilter.validate(&filter(Some("%d-%m-%Q"))).is_err());
+    for output in ["%z", "%Z", "%:z", "%Y-%m-%d %z"] {
+        assert_eq!(DateFilter.validate(&filter(Some(&format!("%d-%m-%Y:{}", output)))).unwrap_err(),
+                   format!("date format '{}' asks for a time zone, which the date does not have", output));
+    }
+
+    let apply = |argument: &str, value: &str| DateFilter.apply(&filter(Some(argument)), value);
+    assert_eq!(apply("%d-%m-%Y:%Y-%m-%d", "07-11-2023").unwrap(),
+               Resolved::Value("2023-11-07".to_string()));
+    assert_eq!(apply("%Y%m%d_%H%M:%Y/%H:%M", "20231107_1403").unwrap(),
+               Resolved::Value("2023/14:03".to_string()));
+    assert_eq!(apply("%d-%m-%Y", "07-11-2023").unwrap(), Resolved::Value("2023-11-07".to_string()));
+    assert_eq!(apply("%d-%m-%Y", "31-02-2023").unwrap_err(),
+               "'31-02-2023' is not a date in the format '%d-%m-%Y'");
+    assert!(apply("%d-%m-%Y:%Y %Z", "07-11-2023").is_err());
+}
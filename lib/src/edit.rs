@@ -0,0 +1,148 @@
+use crate::mass_move::MoveOptions;
+use crate::move_plan::{self, MovePlan};
+use crate::search_by_pattern;
+use std::fs;
+use std::process::Command;
+use tempdir::TempDir;
+
+/// Editor used when neither `$VISUAL` nor `$EDITOR` is set.
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Edit List
+///
+/// Writes the paths of the files to edit one per line. A path containing a line break cannot
+/// be told apart from two paths once edited, so it is an error.
+///
+pub fn edit_list(sources: &[String]) -> Result<String, String> {
+    let mut text = String::new();
+    for source in sources {
+        if source.contains(['\n', '\r']) {
+            return Err(format!("mmv: Not able to edit {:?}: the name contains a line break", source));
+        }
+        text.push_str(source);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Parse Edited List
+///
+/// Compares the edited list with the original paths line by line: every line is the new path
+/// of the file on the same line of the original list, and unchanged lines are left out.
+/// Trailing empty lines, as some editors add, are ignored.
+///
+/// # Arguments
+///
+/// * `sources` - The paths written with `edit_list`.
+/// * `edited` - The list as the editor left it.
+///
+/// # Returns
+///
+/// The source and the destination of every renamed file, or an error if lines were added or
+/// removed, or emptied to delete a file, since files are only renamed.
+///
+pub fn parse_edited_list(sources: &[String], edited: &str) -> Result<Vec<(String, String)>, String> {
+    let mut lines: Vec<&str> = edited.lines().collect();
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    if lines.len() != sources.len() {
+        return Err(format!("mmv: The edited list has {} lines instead of {}; lines must be edited, not added \
+                            or removed", lines.len(), sources.len()));
+    }
+    let mut errors = Vec::new();
+    let mut pairs = Vec::new();
+    for (number, (source, line)) in sources.iter().zip(lines).enumerate() {
+        if line.trim().is_empty() {
+            errors.push(format!("mmv: Line {} of the edited list is empty; {} cannot be deleted", number + 1, source));
+        } else if line != source {
+            pairs.push((source.clone(), line.to_string()));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    Ok(pairs)
+}
+
+/// Run Editor
+///
+/// Writes `text` to a temporary file, opens it in `$VISUAL`, `$EDITOR` or `vi`, and returns
+/// the file as the editor left it. The editor command is run by the shell, so it may carry
+/// arguments, as in `EDITOR="code --wait"`.
+///
+pub fn run_editor(text: &str) -> Result<String, String> {
+    let temp_dir = TempDir::new("mmv")
+        .map_err(|e| format!("mmv: Not able to create a temporary file: {}", e))?;
+    let path = temp_dir.path().join("mmv-edit.txt");
+    fs::write(&path, text).map_err(|e| format!("mmv: Not able to create a temporary file: {}", e))?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .map_err(|e| format!("mmv: Not able to run the editor '{}': {}", editor, e))?;
+    if !status.success() {
+        return Err(format!("mmv: The editor '{}' exited with {}, nothing was moved", editor, status));
+    }
+    fs::read_to_string(&path).map_err(|e| format!("mmv: Not able to read the edited list: {}", e))
+}
+
+/// Plan Edit
+///
+/// Plans renames by editing a list of the files matching the source pattern, like `vidir` or
+/// `qmv`: the list is passed to `edit`, usually `run_editor`, and the edited lines are
+/// planned as an explicit mapping with `move_plan::plan_mapping`, so renames onto each other,
+/// such as swaps, are reported as conflicts.
+///
+/// # Example
+///
+/// ```no
+/// let plan = plan_edit("photos/*.jpg", &MoveOptions::default(), run_editor)?;
+/// execute(&plan, &mut PrintReporter::default())?;
+/// ```
+///
+pub fn plan_edit<F>(source_pattern: &str, options: &MoveOptions, edit: F) -> Result<MovePlan, String>
+    where F: FnOnce(&str) -> Result<String, String> {
+    let sources = search_by_pattern::find_matching_files(source_pattern)?;
+    let edited = edit(&edit_list(&sources)?)?;
+    let pairs = parse_edited_list(&sources, &edited)?;
+    move_plan::plan_mapping(&pairs, options)
+}
+
+
+#[test]
+fn test_parse_edited_list() {
+    let sources = vec!["a.txt".to_string(), "b.txt".to_string(), "c.txt".to_string()];
+    assert_eq!(edit_list(&sources).unwrap(), "a.txt\nb.txt\nc.txt\n");
+    assert!(edit_list(&["a\nb.txt".to_string()]).is_err());
+
+    assert_eq!(parse_edited_list(&sources, "a.txt\nB.txt\nc.txt\n\n").unwrap(),
+               vec![("b.txt".to_string(), "B.txt".to_string())]);
+    assert!(parse_edited_list(&sources, "a.txt\nc.txt\n").unwrap_err()
+        .starts_with("mmv: The edited list has 2 lines instead of 3"));
+    assert_eq!(parse_edited_list(&sources, "a.txt\n\nd.txt\n").unwrap_err(),
+               "mmv: Line 2 of the edited list is empty; b.txt cannot be deleted");
+}
+
+#[test]
+fn test_plan_edit() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(path(name), name).expect("Failed to write file");
+    }
+    let rename = |text: &str| Ok(text.replace("c.txt", "d.txt"));
+    let plan = plan_edit(&path("*.txt"), &MoveOptions::default(), rename).unwrap();
+    assert_eq!(plan.entries.len(), 1);
+    assert_eq!((plan.entries[0].source.clone(), plan.entries[0].destination.clone()), (path("c.txt"), path("d.txt")));
+
+    let swap = |text: &str| Ok(text.replace("a.txt", "@").replace("b.txt", "a.txt").replace('@', "b.txt"));
+    let plan = plan_edit(&path("*.txt"), &MoveOptions::default(), swap).unwrap();
+    assert_eq!(plan.conflicts[0].to_string(),
+               format!("mmv: {} and {} would be swapped", path("a.txt"), path("b.txt")));
+}
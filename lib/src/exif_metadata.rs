@@ -0,0 +1,247 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+use chrono::NaiveDateTime;
+use exif::{In, Tag, Value};
+use serde::{Deserialize, Serialize};
+use crate::file_metadata::{format_naive_date, format_time, validate_naive_date_format};
+use crate::template::{SourceFile, Token};
+use crate::tokens::{sanitize_value, Resolved, TokenProvider};
+
+/// Format of EXIF date and time values.
+const EXIF_DATE_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// Format of EXIF dates without an explicit format.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// EXIF tags that can be used in `{exif:...}` tokens, and whether they hold a date.
+const TAGS: [(&str, Tag, bool); 16] = [
+    ("DateTimeOriginal", Tag::DateTimeOriginal, true),
+    ("DateTimeDigitized", Tag::DateTimeDigitized, true),
+    ("DateTime", Tag::DateTime, true),
+    ("Make", Tag::Make, false),
+    ("Model", Tag::Model, false),
+    ("LensMake", Tag::LensMake, false),
+    ("LensModel", Tag::LensModel, false),
+    ("Software", Tag::Software, false),
+    ("Artist", Tag::Artist, false),
+    ("BodySerialNumber", Tag::BodySerialNumber, false),
+    ("ImageUniqueID", Tag::ImageUniqueID, false),
+    ("Orientation", Tag::Orientation, false),
+    ("PixelXDimension", Tag::PixelXDimension, false),
+    ("PixelYDimension", Tag::PixelYDimension, false),
+    ("PhotographicSensitivity", Tag::PhotographicSensitivity, false),
+    ("FocalLength", Tag::FocalLength, false),
+];
+
+/// What to do with a file that has no value for an `{exif:...}` token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExifFallback {
+    /// Leave the file where it is.
+    Skip,
+    /// Use the modification time of the file instead for date tags, and leave the file where it
+    /// is for other tags.
+    Mtime,
+    /// Fail the whole run.
+    #[default]
+    Error,
+}
+
+impl FromStr for ExifFallback {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ExifFallback, String> {
+        match value {
+            "skip" => Ok(ExifFallback::Skip),
+            "mtime" => Ok(ExifFallback::Mtime),
+            "error" => Ok(ExifFallback::Error),
+            _ => Err(format!("mmv: Unknown EXIF fallback '{}', expected 'skip', 'mtime' or 'error'",
+                             value)),
+        }
+    }
+}
+
+/// EXIF Provider
+///
+/// Resolves `{exif:Tag}` and `{exif:Tag:format}` tokens from the EXIF metadata of JPEG, TIFF,
+/// HEIC, PNG and WebP files, e.g. `{exif:Model}` or `{exif:DateTimeOriginal:%Y%m%d_%H%M%S}`.
+/// Date tags are reformatted with a strftime-style format (`%Y-%m-%d` by default).
+///
+/// A file without the tag is handled according to the configured `ExifFallback`.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExifProvider {
+    pub fallback: ExifFallback,
+}
+
+impl ExifProvider {
+    pub fn new(fallback: ExifFallback) -> ExifProvider {
+        ExifProvider { fallback }
+    }
+}
+
+/// Split Exif Argument
+///
+/// Splits the argument of an `{exif:...}` token into the tag and its optional format.
+///
+fn split_argument(token: &Token) -> Result<(&'static str, Tag, bool, Option<&str>), String> {
+    let argument = token.argument.as_deref()
+        .ok_or_else(|| "expected an EXIF tag, e.g. {exif:Model}".to_string())?;
+    let (name, format) = match argument.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (argument, None),
+    };
+    let (name, tag, is_date) = TAGS.iter()
+        .find(|(known, _, _)| *known == name)
+        .ok_or_else(|| format!("unknown EXIF tag '{}'", name))?;
+    Ok((name, *tag, *is_date, format))
+}
+
+/// Field Text
+///
+/// Converts the value of an EXIF field to text that can be used in a filename.
+///
+fn field_text(field: &exif::Field) -> String {
+    let text = match &field.value {
+        Value::Ascii(values) => values.iter()
+            .map(|value| String::from_utf8_lossy(value).to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => field.display_value().to_string(),
+    };
+    sanitize_value(text.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+}
+
+/// Read Exif Field
+///
+/// Reads the value of a tag from the primary image of a file.
+///
+/// # Returns
+///
+/// The value as text, or `None` if the file has no EXIF metadata or no such tag.
+///
+fn read_field(source: &Path, tag: Tag) -> Result<Option<String>, String> {
+    let file = File::open(source).map_err(|e| e.to_string())?;
+    let exif = match exif::Reader::new().read_from_container(&mut BufReader::new(file)) {
+        Ok(exif) => exif,
+        Err(exif::Error::Io(e)) => return Err(e.to_string()),
+        Err(_) => return Ok(None),
+    };
+    Ok(exif.get_field(tag, In::PRIMARY)
+        .map(field_text)
+        .filter(|text| !text.is_empty()))
+}
+
+impl TokenProvider for ExifProvider {
+    fn provides(&self, name: &str) -> bool {
+        name == "exif"
+    }
+
+    fn validate(&self, token: &Token) -> Result<(), String> {
+        let (name, _, is_date, format) = split_argument(token)?;
+        match format {
+            Some(_) if !is_date => Err(format!("EXIF tag '{}' is not a date and takes no format",
+                                               name)),
+            Some(format) => validate_naive_date_format(format),
+            None => Ok(()),
+        }
+    }
+
+    fn resolve(&self, token: &Token, file: &SourceFile) -> Result<Resolved, String> {
+        let source = file.path;
+        let (name, tag, is_date, format) = split_argument(token)?;
+        let format = format.unwrap_or(DEFAULT_DATE_FORMAT);
+        match read_field(source, tag)? {
+            Some(text) if is_date => {
+                let date = NaiveDateTime::parse_from_str(&text, EXIF_DATE_FORMAT)
+                    .map_err(|_| format!("invalid EXIF date '{}' in tag {}", text, name))?;
+                format_naive_date(&date, format).map(Resolved::Value)
+            }
+            Some(text) => Ok(Resolved::Value(text)),
+            None => match self.fallback {
+                ExifFallback::Skip => Ok(Resolved::Skip(format!("no EXIF tag {}", name))),
+                // Only a date can stand in for a date; other tags are left out as with `skip`
+                ExifFallback::Mtime if !is_date => Ok(Resolved::Skip(format!("no EXIF tag {}", name))),
+                ExifFallback::Mtime => {
+                    let modified = fs::metadata(source)
+                        .and_then(|metadata| metadata.modified())
+                        .map_err(|e| e.to_string())?;
+                    Ok(Resolved::Value(format_time(modified, false, format)))
+                }
+                ExifFallback::Error => Err(format!("no EXIF tag {}", name)),
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+fn write_tiff_with_exif(path: &Path) {
+    use exif::{experimental::Writer, Field};
+    let model = Field {
+        tag: Tag::Model,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![b"Camera/X".to_vec()]),
+    };
+    let date = Field {
+        tag: Tag::DateTimeOriginal,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![b"2023:11:07 14:03:22".to_vec()]),
+    };
+    let strips: [&[u8]; 1] = [&[0u8]];
+    let mut writer = Writer::new();
+    writer.push_field(&model);
+    writer.push_field(&date);
+    writer.set_strips(&strips, In::PRIMARY);
+    let mut buffer = std::io::Cursor::new(Vec::new());
+    writer.write(&mut buffer, false).expect("Failed to write EXIF");
+    fs::write(path, buffer.into_inner()).expect("Failed to write file");
+}
+
+#[test]
+fn test_validate_exif_tokens() {
+    let token = |argument: Option<&str>| Token::new("exif", argument);
+    let provider = ExifProvider::default();
+    assert!(provider.validate(&token(Some("Model"))).is_ok());
+    assert!(provider.validate(&token(Some("DateTimeOriginal:%Y%m%d_%H%M%S"))).is_ok());
+    assert!(provider.validate(&token(None)).is_err());
+    assert!(provider.validate(&token(Some("Modle"))).is_err());
+    assert!(provider.validate(&token(Some("Model:%Y"))).is_err());
+    assert!(provider.validate(&token(Some("DateTime:%Q"))).is_err());
+    assert_eq!(provider.validate(&token(Some("DateTimeOriginal:%z"))).unwrap_err(),
+               "date format '%z' asks for a time zone, which the date does not have");
+}
+
+#[test]
+fn test_resolve_exif_tokens() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let photo = temp_dir.path().join("photo.tif");
+    write_tiff_with_exif(&photo);
+    let plain = temp_dir.path().join("plain.txt");
+    fs::write(&plain, "hello").expect("Failed to write file");
+    let photo_file = SourceFile::new(&photo);
+    let plain_file = SourceFile::new(&plain);
+    let token = |argument: &str| Token::new("exif", Some(argument));
+
+    let provider = ExifProvider::default();
+    assert_eq!(provider.resolve(&token("Model"), &photo_file).unwrap(),
+               Resolved::Value("Camera_X".to_string()));
+    assert_eq!(provider.resolve(&token("DateTimeOriginal:%Y%m%d_%H%M%S"), &photo_file).unwrap(),
+               Resolved::Value("20231107_140322".to_string()));
+    assert_eq!(provider.resolve(&token("Make"), &photo_file).unwrap_err(), "no EXIF tag Make");
+    assert_eq!(provider.resolve(&token("Model"), &plain_file).unwrap_err(), "no EXIF tag Model");
+
+    let provider = ExifProvider::new(ExifFallback::Skip);
+    assert_eq!(provider.resolve(&token("Model"), &plain_file).unwrap(),
+               Resolved::Skip("no EXIF tag Model".to_string()));
+
+    let provider = ExifProvider::new(ExifFallback::Mtime);
+    let modified = fs::metadata(&plain).unwrap().modified().unwrap();
+    assert_eq!(provider.resolve(&token("DateTimeOriginal:%Y%m%d"), &plain_file).unwrap(),
+               Resolved::Value(format_time(modified, false, "%Y%m%d")));
+    assert_eq!(provider.resolve(&token("Model"), &plain_file).unwrap(),
+               Resolved::Skip("no EXIF tag Model".to_string()));
+    assert!(provider.resolve(&token("DateTimeOriginal:%Y %Z"), &photo_file).is_err());
+}
@@ -0,0 +1,257 @@
+use std::fmt::Write;
+use std::fs;
+use std::time::SystemTime;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use crate::template::{SourceFile, Token};
+use crate::tokens::{Resolved, TokenProvider};
+
+/// Default format of date tokens without an explicit format.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// File Metadata Provider
+///
+/// Resolves tokens from the file system metadata of a source file:
+///
+/// * `{mtime}`, `{mtime:%Y/%m}`, `{mtime:utc:%Y%m%d}` - modification time.
+/// * `{ctime:...}` - status change time (creation time on platforms without it).
+/// * `{size}`, `{size:bytes}` - size in bytes; `{size:human}` - size like `ls -h`, e.g. `1.5K`.
+/// * `{inode}` - inode number.
+///
+/// Dates are formatted with strftime-style format strings in local time, unless the format is
+/// prefixed with `utc:`.
+///
+pub struct FileMetadataProvider;
+
+/// Time Zone
+///
+/// Splits an optional `utc:` or `local:` prefix off a date token argument.
+///
+/// # Returns
+///
+/// A flag telling whether the date is formatted in UTC, and the format string.
+///
+fn split_time_zone(argument: Option<&str>) -> (bool, &str) {
+    match argument {
+        None => (false, DEFAULT_DATE_FORMAT),
+        Some(argument) => {
+            if let Some(format) = argument.strip_prefix("utc:") {
+                (true, format)
+            } else if let Some(format) = argument.strip_prefix("local:") {
+                (false, format)
+            } else if argument == "utc" {
+                (true, DEFAULT_DATE_FORMAT)
+            } else if argument == "local" {
+                (false, DEFAULT_DATE_FORMAT)
+            } else {
+                (false, argument)
+            }
+        }
+    }
+}
+
+/// Validate Date Format
+///
+/// Checks that a strftime-style format string contains only known specifiers.
+///
+pub fn validate_date_format(format: &str) -> Result<(), String> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(format!("invalid date format '{}'", format));
+    }
+    Ok(())
+}
+
+/// Validate Naive Date Format
+///
+/// Checks a format for dates without a time zone, such as EXIF dates or dates parsed from a
+/// file name: on top of `validate_date_format`, the format may not ask for a time zone, as
+/// `%z`, `%Z` or `%:z` do.
+///
+pub fn validate_naive_date_format(format: &str) -> Result<(), String> {
+    validate_date_format(format)?;
+    format_naive_date(&NaiveDateTime::default(), format).map(|_| ())
+}
+
+/// Format Naive Date
+///
+/// Formats a date without a time zone with a strftime-style format string.
+///
+/// # Returns
+///
+/// The formatted date, or an error if the format asks for a time zone.
+///
+pub fn format_naive_date(date: &NaiveDateTime, format: &str) -> Result<String, String> {
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format(format))
+        .map_err(|_| format!("date format '{}' asks for a time zone, which the date does not have", format))?;
+    Ok(formatted)
+}
+
+/// Format Time
+///
+/// Formats a point in time with a strftime-style format string, in UTC or in local time.
+///
+/// # Arguments
+///
+/// * `time` - The point in time.
+/// * `utc` - Whether to format the time in UTC rather than local time.
+/// * `format` - A validated strftime-style format string.
+///
+/// # Returns
+///
+/// The formatted time as a `String`.
+///
+pub fn format_time(time: SystemTime, utc: bool, format: &str) -> String {
+    if utc {
+        DateTime::<Utc>::from(time).format(format).to_string()
+    } else {
+        DateTime::<Local>::from(time).format(format).to_string()
+    }
+}
+
+/// Human Size
+///
+/// Formats a size in bytes the way `ls -h` does: `512`, `1.5K`, `23M`.
+///
+/// # Arguments
+///
+/// * `size` - The size in bytes.
+///
+/// # Returns
+///
+/// The formatted size as a `String`.
+///
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if size < 1024 {
+        return size.to_string();
+    }
+    let mut value = size as f64;
+    let mut unit = 0;
+    value /= 1024.0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+#[cfg(unix)]
+fn change_time(metadata: &fs::Metadata) -> std::io::Result<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    use std::time::Duration;
+    let seconds = metadata.ctime();
+    let nanoseconds = metadata.ctime_nsec() as u32;
+    if seconds >= 0 {
+        Ok(SystemTime::UNIX_EPOCH + Duration::new(seconds as u64, nanoseconds))
+    } else {
+        Ok(SystemTime::UNIX_EPOCH - Duration::new(seconds.unsigned_abs(), 0))
+    }
+}
+
+#[cfg(not(unix))]
+fn change_time(metadata: &fs::Metadata) -> std::io::Result<SystemTime> {
+    metadata.created()
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> Result<u64, String> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> Result<u64, String> {
+    Err("inode numbers are not supported on this platform".to_string())
+}
+
+impl TokenProvider for FileMetadataProvider {
+    fn provides(&self, name: &str) -> bool {
+        matches!(name, "mtime" | "ctime" | "size" | "inode")
+    }
+
+    fn validate(&self, token: &Token) -> Result<(), String> {
+        match token.name.as_str() {
+            "mtime" | "ctime" => validate_date_format(split_time_zone(token.argument.as_deref()).1),
+            "size" => match token.argument.as_deref() {
+                None | Some("bytes") | Some("human") => Ok(()),
+                Some(other) => Err(format!("unknown size format '{}', expected 'bytes' or 'human'",
+                                           other)),
+            },
+            _ => match &token.argument {
+                None => Ok(()),
+                Some(_) => Err(format!("token '{}' takes no argument", token.name)),
+            },
+        }
+    }
+
+    fn resolve(&self, token: &Token, file: &SourceFile) -> Result<Resolved, String> {
+        let source = file.path;
+        let metadata = fs::metadata(source).map_err(|e| e.to_string())?;
+        let value = match token.name.as_str() {
+            "mtime" | "ctime" => {
+                let time = if token.name == "mtime" {
+                    metadata.modified()
+                } else {
+                    change_time(&metadata)
+                }.map_err(|e| e.to_string())?;
+                let (utc, format) = split_time_zone(token.argument.as_deref());
+                format_time(time, utc, format)
+            }
+            "size" => match token.argument.as_deref() {
+                Some("human") => human_size(metadata.len()),
+                _ => metadata.len().to_string(),
+            },
+            _ => inode(&metadata)?.to_string(),
+        };
+        Ok(Resolved::Value(value))
+    }
+}
+
+
+#[test]
+fn test_human_size() {
+    assert_eq!(human_size(0), "0");
+    assert_eq!(human_size(1023), "1023");
+    assert_eq!(human_size(1536), "1.5K");
+    assert_eq!(human_size(10 * 1024), "10K");
+    assert_eq!(human_size(23 * 1024 * 1024 + 1), "23M");
+}
+
+#[test]
+fn test_format_time() {
+    let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_699_999_999);
+    assert_eq!(format_time(time, true, "%Y/%m/%d_%H%M%S"), "2023/11/14_221319");
+    assert_eq!(split_time_zone(Some("utc:%Y")), (true, "%Y"));
+    assert_eq!(split_time_zone(Some("%Y")), (false, "%Y"));
+    assert_eq!(split_time_zone(None), (false, DEFAULT_DATE_FORMAT));
+}
+
+#[test]
+fn test_validate_metadata_tokens() {
+    assert!(FileMetadataProvider.validate(&Token::new("mtime", Some("utc:%Y/%m"))).is_ok());
+    assert!(FileMetadataProvider.validate(&Token::new("mtime", Some("%Q"))).is_err());
+    assert!(FileMetadataProvider.validate(&Token::new("size", Some("human"))).is_ok());
+    assert!(FileMetadataProvider.validate(&Token::new("size", Some("kb"))).is_err());
+    assert!(FileMetadataProvider.validate(&Token::new("inode", Some("x"))).is_err());
+}
+
+#[test]
+fn test_resolve_metadata_tokens() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("file.txt");
+    fs::write(&path, vec![0u8; 2048]).expect("Failed to write file");
+    let file = SourceFile::new(&path);
+    let missing = temp_dir.path().join("missing");
+    assert_eq!(FileMetadataProvider.resolve(&Token::new("size", None), &file).unwrap(),
+               Resolved::Value("2048".to_string()));
+    assert_eq!(FileMetadataProvider.resolve(&Token::new("size", Some("human")), &file).unwrap(),
+               Resolved::Value("2.0K".to_string()));
+    let year = FileMetadataProvider.resolve(&Token::new("mtime", Some("utc:%Y")), &file).unwrap();
+    assert_eq!(year, Resolved::Value(Utc::now().format("%Y").to_string()));
+    assert!(FileMetadataProvider.resolve(&Token::new("size", None), &SourceFile::new(&missing)).is_err());
+}
@@ -0,0 +1,187 @@
+use crate::mass_move::MoveOptions;
+use crate::move_plan::{self, MovePlan, PlanEntry};
+use std::io::{self, BufRead, Write};
+
+/// An answer to the question whether to carry out one planned move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    /// Move the file.
+    Yes,
+    /// Leave the file where it is.
+    No,
+    /// Move the file and all remaining ones without asking.
+    All,
+    /// Leave the file and all remaining ones where they are.
+    Quit,
+    /// Move the file to another destination, which is checked before asking again.
+    Edit(String),
+}
+
+/// Check Entry
+///
+/// Checks the destination of a single entry on its own, as planned by `move_plan::plan_mapping`.
+///
+/// # Returns
+///
+/// The entry as planned and the problems with it; the entry is `None` if it cannot be moved
+/// at all, e.g. because its destination exists and is to be skipped.
+///
+fn check_entry(source: &str, destination: &str,
+               options: &MoveOptions) -> (Option<PlanEntry>, Vec<String>) {
+    match move_plan::plan_mapping(&[(source.to_string(), destination.to_string())], options) {
+        Ok(plan) => {
+            let mut problems = plan.warnings;
+            problems.extend(plan.conflicts.iter().map(|conflict| conflict.to_string()));
+            problems.extend(plan.skipped.iter()
+                .map(|skipped| format!("mmv: Skipping {}: {}", skipped.source, skipped.reason)));
+            (plan.entries.into_iter().next(), problems)
+        }
+        Err(e) => (None, vec![e]),
+    }
+}
+
+/// Review
+///
+/// Asks about every entry of a plan, in order, whether to carry it out, passing the problems
+/// found with it so far to `ask`. An edited destination is checked again on its own, and the
+/// entry is asked about again with the new destination and its problems. The accepted moves
+/// are planned anew with `move_plan::plan_mapping`, so all conflict checks are repeated over
+/// the final set of moves.
+///
+/// # Arguments
+///
+/// * `plan` - The plan to review.
+/// * `options` - The options the moves are planned with again, in the mode of `plan`.
+/// * `ask` - Asks about one entry, given the problems with it, e.g. `ask_terminal`.
+///
+/// # Returns
+///
+/// The plan of the accepted moves, keeping the skipped files and the warnings of `plan`.
+///
+pub fn review<F>(plan: &MovePlan, options: &MoveOptions, mut ask: F) -> Result<MovePlan, String>
+    where F: FnMut(&PlanEntry, &[String]) -> Result<Answer, String> {
+    // A stored plan is carried out in the mode it was planned with, whatever the options say
+    let options = &MoveOptions { mode: plan.mode, ..options.clone() };
+    let mut accepted = Vec::new();
+    let mut all = false;
+    'entries: for planned in &plan.entries {
+        let mut entry = planned.clone();
+        let mut problems: Vec<String> = plan.conflicts.iter()
+            .filter(|conflict| conflict.involves(&entry.source))
+            .map(|conflict| conflict.to_string())
+            .collect();
+        loop {
+            let answer = if all { Answer::Yes } else { ask(&entry, &problems)? };
+            match answer {
+                Answer::Yes => accepted.push((entry.source.clone(), entry.destination.clone())),
+                Answer::No => {}
+                Answer::All => {
+                    all = true;
+                    accepted.push((entry.source.clone(), entry.destination.clone()));
+                }
+                Answer::Quit => break 'entries,
+                Answer::Edit(destination) => {
+                    let (checked, checked_problems) = check_entry(&entry.source, &destination, options);
+                    // An unusable destination is shown as typed, so that it can be edited again
+                    entry = checked.unwrap_or(PlanEntry { destination, ..entry });
+                    problems = checked_problems;
+                    continue;
+                }
+            }
+            break;
+        }
+    }
+    let mut reviewed = move_plan::plan_mapping(&accepted, options)?;
+    reviewed.skipped.splice(0..0, plan.skipped.iter().cloned());
+    reviewed.warnings.splice(0..0, plan.warnings.iter().cloned());
+    Ok(reviewed)
+}
+
+/// Ask Terminal
+///
+/// Asks about one move on the terminal, as `src -> dst? [y/n/a/q/e]` on stderr, after printing
+/// the problems with it. `e` asks for a new destination, keeping the current one if the answer
+/// is empty. The end of input is taken as `q`.
+///
+pub fn ask_terminal(entry: &PlanEntry, problems: &[String]) -> Result<Answer, String> {
+    let stdin = io::stdin();
+    let read_line = |prompt: &str| -> Result<Option<String>, String> {
+        eprint!("{}", prompt);
+        io::stderr().flush().map_err(|e| format!("mmv: Not able to ask: {}", e))?;
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end_matches(['\n', '\r']).to_string())),
+            Err(e) => Err(format!("mmv: Not able to read the answer: {}", e)),
+        }
+    };
+    for problem in problems {
+        eprintln!("{}", problem);
+    }
+    loop {
+        let Some(answer) = read_line(&format!("{} -> {}? [y/n/a/q/e] ", entry.source, entry.destination))? else {
+            return Ok(Answer::Quit);
+        };
+        match answer.trim() {
+            "y" => return Ok(Answer::Yes),
+            "n" => return Ok(Answer::No),
+            "a" => return Ok(Answer::All),
+            "q" => return Ok(Answer::Quit),
+            "e" => {
+                let Some(destination) = read_line(&format!("New destination [{}]: ", entry.destination))? else {
+                    return Ok(Answer::Quit);
+                };
+                return Ok(match destination.is_empty() {
+                    true => Answer::Edit(entry.destination.clone()),
+                    false => Answer::Edit(destination),
+                });
+            }
+            _ => eprintln!("y - move, n - skip, a - move this and all remaining, q - quit, e - edit the destination"),
+        }
+    }
+}
+
+
+#[test]
+fn test_review() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    for name in ["a", "b", "c", "d", "taken"] {
+        std::fs::write(path(name), name).expect("Failed to write file");
+    }
+    let pairs: Vec<(String, String)> = ["a", "b", "c", "d"].iter()
+        .map(|name| (path(name), path(&format!("{}.txt", name))))
+        .collect();
+    let plan = move_plan::plan_mapping(&pairs, &MoveOptions::default()).unwrap();
+    let mut answers = vec![
+        Answer::No,
+        Answer::Edit(path("taken")),
+        Answer::Edit(path("new_b")),
+        Answer::Yes,
+        Answer::All,
+    ].into_iter();
+    let mut asked = Vec::new();
+    let ask = |entry: &PlanEntry, problems: &[String]| {
+        asked.push((entry.destination.clone(), problems.to_vec()));
+        Ok(answers.next().unwrap())
+    };
+
+    let reviewed = review(&plan, &MoveOptions::default(), ask).unwrap();
+
+    let moves: Vec<(String, String)> = reviewed.entries.iter()
+        .map(|entry| (entry.source.clone(), entry.destination.clone()))
+        .collect();
+    assert_eq!(moves, vec![(path("b"), path("new_b")), (path("c"), path("c.txt")), (path("d"), path("d.txt"))]);
+    assert!(reviewed.conflicts.is_empty());
+    assert_eq!(asked.len(), 5);
+    assert_eq!(asked[2], (path("taken"), vec![format!("mmv: Not able to replace existing file: {}", path("taken"))]));
+    assert_eq!(asked[3], (path("new_b"), vec![]));
+
+    let quit = |_: &PlanEntry, _: &[String]| Ok(Answer::Quit);
+    assert!(review(&plan, &MoveOptions::default(), quit).unwrap().entries.is_empty());
+
+    let copying = MoveOptions { mode: move_plan::Mode::Copy, ..MoveOptions::default() };
+    let plan = move_plan::plan_mapping(&pairs, &copying).unwrap();
+    let all = |_: &PlanEntry, _: &[String]| Ok(Answer::All);
+    assert_eq!(review(&plan, &MoveOptions::default(), all).unwrap().mode, move_plan::Mode::Copy);
+}
@@ -1,3 +1,22 @@
 pub mod mass_move;
 pub mod build_target_path;
-pub mod search_by_pattern;
\ No newline at end of file
+pub mod search_by_pattern;
+pub mod template;
+pub mod tokens;
+pub mod file_metadata;
+pub mod exif_metadata;
+pub mod audio_tags;
+pub mod content_hash;
+pub mod path_context;
+pub mod date_filter;
+pub mod transliteration;
+pub mod value_map;
+pub mod sanitize;
+pub mod portable;
+pub mod move_plan;
+pub mod reporter;
+pub mod mapping;
+pub mod edit;
+pub mod interactive;
+pub mod curation;
+pub mod report_format;
\ No newline at end of file
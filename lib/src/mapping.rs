@@ -0,0 +1,115 @@
+use std::path::Path;
+use std::str::FromStr;
+use crate::value_map::split_record;
+
+/// How the pairs of an explicit mapping are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MappingFormat {
+    /// A source and a destination separated by a tab on every line.
+    #[default]
+    Tsv,
+    /// A source and a destination separated by a comma on every line, optionally quoted.
+    Csv,
+    /// Sources and destinations alternating, each terminated by a NUL character, as written
+    /// by `find -print0` or `xargs -0` tooling. Allows any character in paths.
+    Nul,
+}
+
+impl FromStr for MappingFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<MappingFormat, String> {
+        match value {
+            "tsv" => Ok(MappingFormat::Tsv),
+            "csv" => Ok(MappingFormat::Csv),
+            "nul" => Ok(MappingFormat::Nul),
+            _ => Err(format!("mmv: Unknown mapping format '{}', expected 'tsv', 'csv' or 'nul'", value)),
+        }
+    }
+}
+
+impl MappingFormat {
+    /// The format of a mapping file judging by its extension: CSV for `.csv`, TSV otherwise.
+    pub fn of(path: &Path) -> MappingFormat {
+        match path.extension().is_some_and(|extension| extension == "csv") {
+            true => MappingFormat::Csv,
+            false => MappingFormat::Tsv,
+        }
+    }
+}
+
+/// Parse Mapping
+///
+/// Reads explicit `(source, destination)` pairs. Empty lines of TSV and CSV mappings are
+/// ignored, empty paths are errors, and so are columns after the second one; fields are taken as they are, without
+/// trimming spaces, since file names may contain them.
+///
+/// # Arguments
+///
+/// * `text` - The mapping.
+/// * `format` - How the pairs are written.
+///
+/// # Returns
+///
+/// The pairs in the order they were given, or an error naming the first malformed line.
+///
+/// # Example
+///
+/// ```no
+/// let pairs = parse_mapping("IMG_0001.jpg\tbeach.jpg\n", MappingFormat::Tsv)?;
+/// assert_eq!(pairs, vec![("IMG_0001.jpg".to_string(), "beach.jpg".to_string())]);
+/// ```
+///
+pub fn parse_mapping(text: &str, format: MappingFormat) -> Result<Vec<(String, String)>, String> {
+    if format == MappingFormat::Nul {
+        if text.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fields: Vec<&str> = text.strip_suffix('\0').unwrap_or(text).split('\0').collect();
+        // A doubled NUL leaves an empty path, which would shift every later pair
+        if let Some(empty) = fields.iter().position(|field| field.is_empty()) {
+            return Err(format!("mmv: Invalid mapping record {}: expected a source and a destination",
+                               empty / 2 + 1));
+        }
+        if !fields.len().is_multiple_of(2) {
+            return Err(format!("mmv: Invalid mapping: {} NUL-separated paths, expected pairs of a source and a destination",
+                               fields.len()));
+        }
+        return Ok(fields.chunks(2).map(|pair| (pair[0].to_string(), pair[1].to_string())).collect());
+    }
+    let mut pairs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("mmv: Invalid mapping line {}: {}", number + 1, message);
+        let fields = split_record(line, format == MappingFormat::Tsv).map_err(|message| error(&message))?;
+        match fields.as_slice() {
+            [source, destination, ..] if !source.is_empty() && !destination.is_empty() =>
+                pairs.push((source.clone(), destination.clone())),
+            _ => return Err(error("expected a source and a destination")),
+        }
+    }
+    Ok(pairs)
+}
+
+
+#[test]
+fn test_parse_mapping() {
+    let pair = |source: &str, destination: &str| (source.to_string(), destination.to_string());
+    assert_eq!(parse_mapping("a.txt\tb c.txt\r\n\nd.txt\te.txt\tnote\n", MappingFormat::Tsv).unwrap(),
+               vec![pair("a.txt", "b c.txt"), pair("d.txt", "e.txt")]);
+    assert_eq!(parse_mapping("a.txt,\"b, c.txt\"\n", MappingFormat::Csv).unwrap(),
+               vec![pair("a.txt", "b, c.txt")]);
+    assert_eq!(parse_mapping("a\ttab.txt\0b\nnewline.txt\0", MappingFormat::Nul).unwrap(),
+               vec![pair("a\ttab.txt", "b\nnewline.txt")]);
+    assert_eq!(parse_mapping("", MappingFormat::Nul).unwrap(), vec![]);
+    assert_eq!(parse_mapping("a.txt\tb.txt\nc.txt\n", MappingFormat::Tsv).unwrap_err(),
+               "mmv: Invalid mapping line 2: expected a source and a destination");
+    assert!(parse_mapping("a.txt\0b.txt\0c.txt\0", MappingFormat::Nul).unwrap_err().contains("3 NUL-separated paths"));
+    assert_eq!(parse_mapping("a.txt\0b.txt\0c.txt\0\0d.txt\0", MappingFormat::Nul).unwrap_err(),
+               "mmv: Invalid mapping record 2: expected a source and a destination");
+    assert!(parse_mapping("\0a.txt\0", MappingFormat::Nul).unwrap_err().contains("record 1:"));
+    assert_eq!(MappingFormat::of(Path::new("renames.csv")), MappingFormat::Csv);
+}
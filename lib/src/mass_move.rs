@@ -1,6 +1,26 @@
-use crate::build_target_path;
-use crate::search_by_pattern;
-use std::path::Path;
+use crate::exif_metadata::{ExifFallback, ExifProvider};
+use crate::move_plan::{self, Mode, MovePlan, OnConflict};
+use crate::reporter::{PrintReporter, Reporter};
+use crate::template::Filter;
+use crate::tokens::Tokens;
+use crate::transliteration::{TranslitFilter, TranslitTable};
+use crate::value_map::{MapFilter, MissingKey};
+use serde::{Deserialize, Serialize};
+
+/// Options of a mass move beyond the source and the destination pattern.
+#[derive(Debug, Clone, Default)]
+pub struct MoveOptions {
+    /// What to do with files whose destination already exists.
+    pub on_conflict: OnConflict,
+    /// Whether files are moved or copied.
+    pub mode: Mode,
+    /// Filters applied to the stem and the extension of every destination file name, see
+    /// `Template::with_name_filters`.
+    pub name_filters: Vec<Filter>,
+    /// Fail unless every destination can be used on Windows and macOS, see
+    /// `portable::check_portable`.
+    pub portable: bool,
+}
 
 /// Mass move files that match a source pattern to a destination pattern.
 ///
@@ -22,51 +42,282 @@ use std::path::Path;
 /// mass_move("path/to/some_*_filename.*", "path2/to/changed_#1_filename.#2", true);
 /// ```
 ///
-/// This function will display the original file paths and their paths after the move, and it will
-/// also move the files accordingly, overwriting existing files if the `-f` flag is specified.
+/// Markers may appear in any component of the destination pattern; every computed parent
+/// directory is created as needed.
+///
+/// Tokens such as `{mtime:%Y}`, `{size:human}`, `{exif:Model}`, `{tag:artist}`, `{sha256:12}` or
+/// `{parent}` are resolved for every source file by the built-in token providers, for many files
+/// in parallel. A source pattern like `photos/**/*.jpg` matches files in all subdirectories.
+///
+/// The destination pattern is parsed and validated against the number of wildcards in the
+/// source pattern before any file is touched, so malformed or out-of-range markers are reported
+/// as errors instead of failing in the middle of a run. Computed destinations are sanitized
+/// the same way: control characters are replaced and names longer than `NAME_MAX` truncated,
+/// and all problems of all files are reported before the first move.
+///
+/// This function will display the original file paths and their paths after the move with
+/// `PrintReporter`, and it will also move the files accordingly, overwriting existing files if
+/// the `-f` flag is specified.
 ///
 pub fn mass_move(source_pattern: &str, destination_pattern: &str, force: bool) -> Result<(), String> {
-    let result_source_files = search_by_pattern::find_matching_files(source_pattern);
-    let source_files;
-    match result_source_files {
-        Ok(_) => source_files = result_source_files.unwrap(),
-        Err(error) => return Err(format!("{}", error)),
-    }
-    let mut destination_paths = Vec::new();
-    for source_file_with_path in source_files {
-        let source_path = Path::new(&source_file_with_path);
-        let parts_of_new_filename = build_target_path::extract_generic_parts(
-            &source_file_with_path, source_pattern);
-        let destination_path_filename = build_target_path::build_target_path(
-            parts_of_new_filename, destination_pattern);
-        let destination_path = Path::new(&destination_path_filename);
-        if destination_path.exists() {
-            if !force {
-                return Err(format!("mmv: Not able to replace existing file: {}", destination_path.to_string_lossy()));
-            } else {
-                match std::fs::remove_file(destination_path) {
-                    Ok(_) => destination_paths.push((source_path.to_path_buf(), destination_path.to_path_buf())),
-                    Err(_) => return Err(format!("mmv: Not able to replace existing file")),
-                }
-            }
-        } else {
-            destination_paths.push((source_path.to_path_buf(), destination_path.to_path_buf()));
+    mass_move_with_tokens(source_pattern, destination_pattern, force, &Tokens::default())
+}
+
+/// Mass move files like `mass_move`, resolving template tokens with the given providers.
+///
+/// # Arguments
+///
+/// * `source_pattern` - A string representing the pattern to match source files.
+/// * `destination_pattern` - A string representing the pattern to generate destination paths.
+/// * `force` - A boolean flag indicating whether to replace existing files (if `true`).
+/// * `tokens` - The token providers available to the destination pattern.
+///
+/// # Example
+///
+/// ```no
+/// let tokens = Tokens::default()
+///     .with_provider(Box::new(ExifProvider::new(ExifFallback::Mtime)));
+/// mass_move_with_tokens("photos/*.jpg", "{exif:DateTimeOriginal:%Y%m%d_%H%M%S}.jpg", false, &tokens);
+/// ```
+///
+/// Files for which a token resolves to `Resolved::Skip` are reported and left where they are.
+///
+pub fn mass_move_with_tokens(source_pattern: &str, destination_pattern: &str, force: bool,
+                             tokens: &Tokens) -> Result<(), String> {
+    let options = MoveOptions { on_conflict: OnConflict::from_force(force), ..MoveOptions::default() };
+    mass_move_with_options(source_pattern, destination_pattern, &options, tokens, &mut PrintReporter::default())
+}
+
+/// Mass move files like `mass_move_with_tokens`, with all options given as `MoveOptions`.
+///
+/// # Arguments
+///
+/// * `source_pattern` - A string representing the pattern to match source files.
+/// * `destination_pattern` - A string representing the pattern to generate destination paths.
+/// * `options` - What to do with existing destinations, whether to move or copy, filters for
+///   destination file names and whether destinations must be portable.
+/// * `tokens` - The token and filter providers available to the destination pattern.
+/// * `reporter` - Receives the progress of the run, `PrintReporter` for the output of `mmv`.
+///
+/// # Example
+///
+/// ```no
+/// let options = MoveOptions {
+///     name_filters: vec![Filter::parse("slug")?],
+///     portable: true,
+///     ..MoveOptions::default()
+/// };
+/// mass_move_with_options("archive/*", "export/#1", &options, &Tokens::default(),
+///                        &mut PrintReporter::default());
+/// ```
+///
+/// With `portable` set, reserved names such as `CON`, characters such as `:`, trailing dots and
+/// spaces, and destinations differing only in case fail the run before the first move.
+///
+/// This is `move_plan::plan` followed by `move_plan::execute`; library callers can call both
+/// themselves to inspect or filter the plan.
+///
+pub fn mass_move_with_options(source_pattern: &str, destination_pattern: &str, options: &MoveOptions,
+                              tokens: &Tokens, reporter: &mut dyn Reporter) -> Result<(), String> {
+    let plan = move_plan::plan(source_pattern, destination_pattern, options, tokens)?;
+    move_plan::execute(&plan, reporter)
+}
+
+/// Mass Move
+///
+/// A mass move job with the options of the `mmv` command that decide what is moved where,
+/// built step by step and serializable so that a job can be stored and run again later. Token
+/// providers and filters are described by their settings, such as the EXIF fallback or the map
+/// files, and are set up when the job is planned.
+///
+/// How a run is reported and reviewed is up to the caller: the output format and quoting,
+/// `--json` and `--print0` are chosen with the reporter passed to `run`, and `--interactive`
+/// reviews the plan with `interactive::review` before it is executed.
+///
+/// # Example
+///
+/// ```no
+/// let job = MassMove::new("photos/*.jpg", "sorted/{exif:DateTimeOriginal:%Y}/#1.jpg")
+///     .mode(Mode::Copy)
+///     .on_conflict(OnConflict::Skip)
+///     .exif_fallback(ExifFallback::Mtime)
+///     .dry_run(true);
+/// job.run(&mut PrintReporter::new(job.is_dry_run()))?;
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MassMove {
+    source_pattern: String,
+    destination_pattern: String,
+    on_conflict: OnConflict,
+    mode: Mode,
+    dry_run: bool,
+    portable: bool,
+    name_filters: Vec<String>,
+    exif_fallback: ExifFallback,
+    translit_table: TranslitTable,
+    maps: Vec<String>,
+    map_missing: MissingKey,
+}
+
+impl MassMove {
+    pub fn new(source_pattern: &str, destination_pattern: &str) -> MassMove {
+        MassMove {
+            source_pattern: source_pattern.to_string(),
+            destination_pattern: destination_pattern.to_string(),
+            ..MassMove::default()
         }
     }
-    let (directory, _) = search_by_pattern::parse_full_path(destination_pattern);
-    let path = Path::new(directory);
 
-    if !path.exists() {
-        std::fs::create_dir_all(path).expect("mmv: Not able to move file");
+    /// Replaces existing files if `true`, the same as `on_conflict(OnConflict::Overwrite)`.
+    pub fn force(self, force: bool) -> MassMove {
+        self.on_conflict(OnConflict::from_force(force))
+    }
+
+    /// Sets what to do with files whose destination already exists.
+    pub fn on_conflict(mut self, on_conflict: OnConflict) -> MassMove {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Sets whether files are moved or copied.
+    pub fn mode(mut self, mode: Mode) -> MassMove {
+        self.mode = mode;
+        self
+    }
+
+    /// Only reports what would be done if `true`.
+    pub fn dry_run(mut self, dry_run: bool) -> MassMove {
+        self.dry_run = dry_run;
+        self
     }
-    for source_destination_paths in destination_paths {
-        match std::fs::rename(Path::new(&source_destination_paths.0),
-                              Path::new(&source_destination_paths.1)) {
-            Ok(_) => println!("{} -> {}", &source_destination_paths.0.to_string_lossy(),
-                              &source_destination_paths.1.to_string_lossy()),
-            Err(e) => return Err(format!("Error: {}", e)),
+
+    /// Fails unless every destination can be used on Windows and macOS if `true`.
+    pub fn portable(mut self, portable: bool) -> MassMove {
+        self.portable = portable;
+        self
+    }
+
+    /// Adds a filter such as `slug` for every destination file name, see `--name-filter`.
+    pub fn name_filter(mut self, filter: &str) -> MassMove {
+        self.name_filters.push(filter.to_string());
+        self
+    }
+
+    /// Sets what to do with files without a requested EXIF tag.
+    pub fn exif_fallback(mut self, exif_fallback: ExifFallback) -> MassMove {
+        self.exif_fallback = exif_fallback;
+        self
+    }
+
+    /// Sets the default table of the `translit` and `slug` filters.
+    pub fn translit_table(mut self, translit_table: TranslitTable) -> MassMove {
+        self.translit_table = translit_table;
+        self
+    }
+
+    /// Adds a map for `|map:name` filters, given as `name=file.csv`.
+    pub fn map(mut self, spec: &str) -> MassMove {
+        self.maps.push(spec.to_string());
+        self
+    }
+
+    /// Sets what to do with values missing from a map.
+    pub fn map_missing(mut self, map_missing: MissingKey) -> MassMove {
+        self.map_missing = map_missing;
+        self
+    }
+
+    /// Replaces the source and destination patterns, keeping every other option.
+    pub fn patterns(mut self, source_pattern: &str, destination_pattern: &str) -> MassMove {
+        self.source_pattern = source_pattern.to_string();
+        self.destination_pattern = destination_pattern.to_string();
+        self
+    }
+
+    pub fn source_pattern(&self) -> &str {
+        &self.source_pattern
+    }
+
+    pub fn destination_pattern(&self) -> &str {
+        &self.destination_pattern
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Sets up the token providers and filters of the job, loading its maps.
+    pub fn tokens(&self) -> Result<Tokens, String> {
+        let mut map_filter = MapFilter::new(self.map_missing);
+        for spec in &self.maps {
+            map_filter = map_filter.with_map_file(spec)?;
+        }
+        Ok(Tokens::default()
+            .with_provider(Box::new(ExifProvider::new(self.exif_fallback)))
+            .with_filter(Box::new(TranslitFilter::new(self.translit_table)))
+            .with_filter(Box::new(map_filter)))
+    }
+
+    /// Returns the options of the job, parsing its name filters.
+    pub fn options(&self) -> Result<MoveOptions, String> {
+        let name_filters = self.name_filters.iter()
+            .map(|spec| Filter::parse(spec))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(MoveOptions {
+            on_conflict: self.on_conflict,
+            mode: self.mode,
+            name_filters,
+            portable: self.portable,
+        })
+    }
+
+    /// Computes the plan of the job without moving anything, see `move_plan::plan`.
+    pub fn plan(&self) -> Result<MovePlan, String> {
+        move_plan::plan(&self.source_pattern, &self.destination_pattern, &self.options()?,
+                        &self.tokens()?)
+    }
+
+    /// Run
+    ///
+    /// Plans the job and carries the plan out, or only previews it for a dry run.
+    ///
+    /// # Arguments
+    ///
+    /// * `reporter` - Receives the progress of the run.
+    ///
+    pub fn run(&self, reporter: &mut dyn Reporter) -> Result<(), String> {
+        let plan = self.plan()?;
+        if self.dry_run {
+            move_plan::preview(&plan, reporter)
+        } else {
+            move_plan::execute(&plan, reporter)
         }
     }
-    Ok(())
 }
 
+
+#[test]
+fn test_mass_move_builder() {
+    let job = MassMove::new("in/*.jpg", "out/#1.jpg")
+        .force(true)
+        .mode(Mode::Copy)
+        .dry_run(true)
+        .name_filter("slug")
+        .exif_fallback(ExifFallback::Mtime)
+        .map("customers=customers.csv");
+    let options = job.options().unwrap();
+    assert_eq!(options.on_conflict, OnConflict::Overwrite);
+    assert_eq!(options.mode, Mode::Copy);
+    assert_eq!(options.name_filters[0].name, "slug");
+    assert!(job.is_dry_run());
+    assert!(matches!(job.tokens(), Err(e) if e.contains("customers.csv")));
+
+    let json = serde_json::to_string(&job).unwrap();
+    assert!(json.contains(r#""on_conflict":"overwrite","mode":"copy","dry_run":true"#));
+    assert_eq!(serde_json::from_str::<MassMove>(&json).unwrap(), job);
+    assert_eq!(serde_json::from_str::<MassMove>(r#"{"source_pattern":"a*","destination_pattern":"b#1"}"#)
+                   .unwrap(), MassMove::new("a*", "b#1"));
+    assert!(MassMove::new("a*", "b#1").name_filter(":icao").options().is_err());
+}
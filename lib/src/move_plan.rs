@@ -0,0 +1,1007 @@
+use crate::build_target_path;
+use crate::mass_move::MoveOptions;
+use crate::portable;
+use crate::reporter::Reporter;
+use crate::sanitize;
+use crate::search_by_pattern;
+use crate::template::{SourceFile, Template};
+use crate::tokens::{Resolved, Tokens};
+use crate::value_map::split_record;
+use chrono::{DateTime, SecondsFormat, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// What to do with a file whose destination already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Fail the whole run before any file is moved.
+    #[default]
+    Error,
+    /// Leave the file where it is.
+    Skip,
+    /// Replace the existing file, as with `-f`.
+    Overwrite,
+}
+
+impl OnConflict {
+    /// The policy of the `force` flag: replace existing files, or fail.
+    pub fn from_force(force: bool) -> OnConflict {
+        if force { OnConflict::Overwrite } else { OnConflict::Error }
+    }
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<OnConflict, String> {
+        match value {
+            "error" => Ok(OnConflict::Error),
+            "skip" => Ok(OnConflict::Skip),
+            "overwrite" => Ok(OnConflict::Overwrite),
+            _ => Err(format!("mmv: Unknown conflict policy '{}', expected 'error', 'skip' or 'overwrite'",
+                             value)),
+        }
+    }
+}
+
+/// How files get to their destinations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Rename the files.
+    #[default]
+    Move,
+    /// Copy the files, leaving the originals where they are.
+    Copy,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Mode, String> {
+        match value {
+            "move" => Ok(Mode::Move),
+            "copy" => Ok(Mode::Copy),
+            _ => Err(format!("mmv: Unknown mode '{}', expected 'move' or 'copy'", value)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Move => write!(f, "move"),
+            Mode::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+/// File Identity
+///
+/// What a source file looked like when it was planned, to tell whether it is still the same
+/// file with the same contents when a stored plan is applied. The inode is 0 on systems
+/// without inodes.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    pub inode: u64,
+    pub size: u64,
+    /// The modification time in RFC 3339 format with nanoseconds, in UTC.
+    pub mtime: String,
+}
+
+impl FileIdentity {
+    /// Reads the identity of the file at `path`.
+    pub fn of(path: &Path) -> Result<FileIdentity, String> {
+        let metadata = fs::metadata(path)
+            .map_err(|e| format!("mmv: Not able to read {}: {}", path.to_string_lossy(), e))?;
+        let mtime = metadata.modified()
+            .map_err(|e| format!("mmv: Not able to read {}: {}", path.to_string_lossy(), e))?;
+        #[cfg(unix)]
+        let inode = metadata.ino();
+        #[cfg(not(unix))]
+        let inode = 0;
+        Ok(FileIdentity {
+            inode,
+            size: metadata.len(),
+            mtime: DateTime::<Utc>::from(mtime).to_rfc3339_opts(SecondsFormat::Nanos, true),
+        })
+    }
+
+    /// Describes how `current` differs from this identity.
+    fn differences(&self, current: &FileIdentity) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.inode != current.inode {
+            differences.push(format!("inode {} is now {}", self.inode, current.inode));
+        }
+        if self.size != current.size {
+            differences.push(format!("size {} is now {}", self.size, current.size));
+        }
+        if self.mtime != current.mtime {
+            differences.push(format!("mtime {} is now {}", self.mtime, current.mtime));
+        }
+        differences
+    }
+}
+
+/// One file to move.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub source: String,
+    pub destination: String,
+    /// The destination exists and is replaced, as requested with `OnConflict::Overwrite`.
+    #[serde(default)]
+    pub replace: bool,
+    /// The source file as it was planned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<FileIdentity>,
+}
+
+/// A file left where it is because its destination could not be computed or already exists.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Skipped {
+    pub source: String,
+    pub reason: String,
+}
+
+/// A reason the planned moves cannot be carried out as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Conflict {
+    /// The destination of a file already exists and the policy is `OnConflict::Error`.
+    Exists { source: String, destination: String },
+    /// Several files would be moved to the same destination.
+    Duplicate { sources: Vec<String>, destination: String },
+    /// The destination of a file is the source of another move, and would be replaced before
+    /// that file is moved away.
+    Chain { source: String, destination: String },
+    /// Files would be moved to each other's sources in a cycle, as in a swap: every source is
+    /// moved to the next one, and the last one to the first.
+    Cycle { sources: Vec<String> },
+}
+
+impl Conflict {
+    /// Returns `true` if the conflict concerns the move of `source`.
+    pub fn involves(&self, source: &str) -> bool {
+        match self {
+            Conflict::Exists { source: conflicting, .. } | Conflict::Chain { source: conflicting, .. } =>
+                conflicting == source,
+            Conflict::Duplicate { sources, .. } | Conflict::Cycle { sources } =>
+                sources.iter().any(|conflicting| conflicting == source),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::Exists { destination, .. } =>
+                write!(f, "mmv: Not able to replace existing file: {}", destination),
+            Conflict::Duplicate { sources, destination } =>
+                write!(f, "mmv: {} would all be moved to {}", sources.join(", "), destination),
+            Conflict::Chain { source, destination } =>
+                write!(f, "mmv: {} would be replaced by {} before it is moved itself", destination, source),
+            Conflict::Cycle { sources } if sources.len() == 2 =>
+                write!(f, "mmv: {} and {} would be swapped", sources[0], sources[1]),
+            Conflict::Cycle { sources } =>
+                write!(f, "mmv: {} -> {} form a cycle", sources.join(" -> "), sources[0]),
+        }
+    }
+}
+
+/// Move Plan
+///
+/// Everything a mass move is going to do, computed without touching any file: the moves, the
+/// conflicts that prevent them, the skipped files and the warnings about fixed destinations.
+/// A plan can be inspected, filtered with `retain` and serialized before it is passed to
+/// `execute`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MovePlan {
+    #[serde(default)]
+    pub mode: Mode,
+    #[serde(default)]
+    pub entries: Vec<PlanEntry>,
+    #[serde(default)]
+    pub conflicts: Vec<Conflict>,
+    #[serde(default)]
+    pub skipped: Vec<Skipped>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl MovePlan {
+    /// Retain
+    ///
+    /// Keeps only the entries for which `keep` returns `true`. Conflicts are updated to the
+    /// remaining entries, so vetoing one of two files with the same destination resolves the
+    /// conflict between them.
+    ///
+    pub fn retain<F: FnMut(&PlanEntry) -> bool>(&mut self, keep: F) {
+        self.entries.retain(keep);
+        let entries = &self.entries;
+        let planned = |source: &str| entries.iter().any(|entry| entry.source == source);
+        self.conflicts = std::mem::take(&mut self.conflicts).into_iter()
+            .flat_map(|conflict| match conflict {
+                Conflict::Exists { ref source, .. } => match planned(source) {
+                    true => vec![conflict],
+                    false => vec![],
+                },
+                Conflict::Duplicate { sources, destination } => {
+                    let sources: Vec<String> = sources.into_iter()
+                        .filter(|source| planned(source))
+                        .collect();
+                    match sources.len() > 1 {
+                        true => vec![Conflict::Duplicate { sources, destination }],
+                        false => vec![],
+                    }
+                }
+                Conflict::Chain { ref source, ref destination } => match planned(source) && planned(destination) {
+                    true => vec![conflict],
+                    false => vec![],
+                },
+                // A cycle without one of its files is broken into chains
+                Conflict::Cycle { sources } => match sources.iter().all(|source| planned(source)) {
+                    true => vec![Conflict::Cycle { sources }],
+                    false => (0..sources.len())
+                        .map(|index| (&sources[index], &sources[(index + 1) % sources.len()]))
+                        .filter(|(source, destination)| planned(source) && planned(destination))
+                        .map(|(source, destination)| Conflict::Chain {
+                            source: source.clone(),
+                            destination: destination.clone(),
+                        })
+                        .collect(),
+                },
+            })
+            .collect();
+    }
+
+    /// Revalidate
+    ///
+    /// Checks a stored plan against the files as they are now, before it is applied: every
+    /// source must still have the identity it was planned with, and the conflicts are checked
+    /// again with the policy of `options`, as destinations may have appeared or disappeared
+    /// since. Destinations may have been edited by hand, so they are sanitized again and, if
+    /// `options` asks for it, checked for portability.
+    ///
+    /// # Returns
+    ///
+    /// An error for every source file that is missing, has changed, or has no identity in the
+    /// plan, as in a plan written or edited by hand, and for every destination that cannot be
+    /// used.
+    ///
+    pub fn revalidate(&mut self, options: &MoveOptions) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for entry in &mut self.entries {
+            entry.destination = sanitize_destination(&entry.source, &entry.destination, &mut self.warnings,
+                                                     &mut errors);
+        }
+        if options.portable {
+            errors.extend(portability_errors(&self.entries, ""));
+        }
+        for entry in &self.entries {
+            let Some(planned) = &entry.identity else {
+                errors.push(format!("mmv: {} has no identity in the plan, so it cannot be checked for \
+                                     changes; plan it again with --plan-out", entry.source));
+                continue;
+            };
+            match FileIdentity::of(Path::new(&entry.source)) {
+                Ok(current) => {
+                    let differences = planned.differences(&current);
+                    if !differences.is_empty() {
+                        errors.push(format!("mmv: {} has changed since it was planned: {}",
+                                            entry.source, differences.join(", ")));
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        self.conflicts.clear();
+        for entry in &mut self.entries {
+            entry.replace = false;
+        }
+        resolve_conflicts(self, options.on_conflict);
+        Ok(())
+    }
+}
+
+/// Chained Moves
+///
+/// Finds the moves whose destination is the source of another move. Moves that lead back to
+/// themselves through such links form a cycle, reported once from its smallest source; all
+/// others are chains.
+///
+fn chained_moves(entries: &[PlanEntry]) -> Vec<Conflict> {
+    // Moves of a file onto itself are skipped before the conflicts are checked
+    let destinations: HashMap<&str, &str> = entries.iter()
+        .filter(|entry| entry.destination != entry.source)
+        .map(|entry| (entry.source.as_str(), entry.destination.as_str()))
+        .collect();
+    // Every source is followed once: `false` while on the current walk, `true` when done
+    let mut visited: HashMap<&str, bool> = HashMap::new();
+    let mut conflicts = Vec::new();
+    let mut in_cycle = HashSet::new();
+    for entry in entries {
+        let mut walk = Vec::new();
+        let mut next = entry.source.as_str();
+        while let (Some(destination), None) = (destinations.get(next), visited.get(next)) {
+            visited.insert(next, false);
+            walk.push(next);
+            next = destination;
+        }
+        if visited.get(next) == Some(&false) {
+            let start = walk.iter().position(|source| *source == next).unwrap_or_default();
+            let mut sources: Vec<String> = walk[start..].iter().map(|source| source.to_string()).collect();
+            let smallest = (0..sources.len()).min_by_key(|index| &sources[*index]).unwrap_or_default();
+            sources.rotate_left(smallest);
+            in_cycle.extend(sources.iter().cloned());
+            conflicts.push(Conflict::Cycle { sources });
+        }
+        for source in walk {
+            visited.insert(source, true);
+        }
+    }
+    for entry in entries {
+        if destinations.contains_key(entry.destination.as_str()) && !in_cycle.contains(&entry.source) {
+            conflicts.push(Conflict::Chain {
+                source: entry.source.clone(),
+                destination: entry.destination.clone(),
+            });
+        }
+    }
+    conflicts
+}
+
+/// Returns `true` if the destination of an entry is its source, so that it has nothing to do.
+fn is_self_move(entry: &PlanEntry) -> bool {
+    if entry.source == entry.destination {
+        return true;
+    }
+    match (fs::canonicalize(&entry.source), fs::canonicalize(&entry.destination)) {
+        (Ok(source), Ok(destination)) => source == destination,
+        _ => false,
+    }
+}
+
+/// Resolve Conflicts
+///
+/// Skips the entries that would move a file onto itself, then checks the others for moves
+/// onto the sources of other moves, for destinations that already exist, handling them
+/// according to `on_conflict`, and for destinations shared by several entries.
+///
+fn resolve_conflicts(plan: &mut MovePlan, on_conflict: OnConflict) {
+    // Replacing the destination of such an entry would delete its source
+    let (unchanged, entries): (Vec<PlanEntry>, Vec<PlanEntry>) = std::mem::take(&mut plan.entries)
+        .into_iter()
+        .partition(is_self_move);
+    plan.entries = entries;
+    plan.skipped.extend(unchanged.into_iter().map(|entry| Skipped {
+        reason: format!("{} is already at its destination", entry.source),
+        source: entry.source,
+    }));
+    let chained = chained_moves(&plan.entries);
+    let chained_sources: HashSet<String> = chained.iter()
+        .flat_map(|conflict| match conflict {
+            Conflict::Chain { source, .. } => vec![source.clone()],
+            Conflict::Cycle { sources } => sources.clone(),
+            _ => vec![],
+        })
+        .collect();
+    plan.conflicts.extend(chained);
+    let mut entries = Vec::new();
+    for mut entry in std::mem::take(&mut plan.entries) {
+        if !chained_sources.contains(&entry.source) && Path::new(&entry.destination).exists() {
+            match on_conflict {
+                OnConflict::Error => plan.conflicts.push(Conflict::Exists {
+                    source: entry.source.clone(),
+                    destination: entry.destination.clone(),
+                }),
+                OnConflict::Skip => {
+                    plan.skipped.push(Skipped {
+                        reason: format!("{} already exists", entry.destination),
+                        source: entry.source,
+                    });
+                    continue;
+                }
+                OnConflict::Overwrite => entry.replace = true,
+            }
+        }
+        entries.push(entry);
+    }
+    plan.entries = entries;
+    let mut sources_by_destination: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for entry in &plan.entries {
+        sources_by_destination.entry(&entry.destination).or_default().push(entry.source.clone());
+    }
+    let duplicates: Vec<Conflict> = sources_by_destination.into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|(destination, sources)| Conflict::Duplicate { sources, destination: destination.to_string() })
+        .collect();
+    plan.conflicts.extend(duplicates);
+}
+
+/// The header of a plan stored as CSV.
+const PLAN_CSV_HEADER: &str = "mode,source,destination,inode,size,mtime";
+
+/// Returns `true` if a plan file is stored as CSV, judging by its `.csv` extension.
+fn is_csv_plan(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "csv")
+}
+
+/// Quotes a CSV field if it contains a comma or a quote, doubling the quotes.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+/// Writes the entries of a plan as CSV, one per line; conflicts, skipped files and warnings are
+/// computed again when the plan is applied.
+fn plan_to_csv(plan: &MovePlan) -> Result<String, String> {
+    let mut csv = format!("{}\n", PLAN_CSV_HEADER);
+    for entry in &plan.entries {
+        let paths = [&entry.source, &entry.destination];
+        if let Some(path) = paths.into_iter().find(|path| path.contains(['\n', '\r'])) {
+            return Err(format!("{:?} contains a line break, use a .json plan", path));
+        }
+        let identity = match &entry.identity {
+            Some(identity) => format!("{},{},{}", identity.inode, identity.size, identity.mtime),
+            None => ",,".to_string(),
+        };
+        csv.push_str(&format!("{},{},{},{}\n", plan.mode, csv_field(&entry.source),
+                              csv_field(&entry.destination), identity));
+    }
+    Ok(csv)
+}
+
+/// Reads the entries of a plan written by `plan_to_csv`. An entry without an inode, a size and
+/// an mtime has no identity.
+fn plan_from_csv(csv: &str) -> Result<MovePlan, String> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    if lines.next().map(|(_, header)| header.trim_end_matches('\r')) != Some(PLAN_CSV_HEADER) {
+        return Err(format!("expected the header '{}'", PLAN_CSV_HEADER));
+    }
+    let mut plan = MovePlan::default();
+    for (number, line) in lines {
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let fields = split_record(line.trim_end_matches('\r'), false).map_err(|message| error(&message))?;
+        let [mode, source, destination, inode, size, mtime] = fields.as_slice() else {
+            return Err(error(&format!("expected {} fields, found {}", 6, fields.len())));
+        };
+        if source.is_empty() || destination.is_empty() {
+            return Err(error("expected a source and a destination"));
+        }
+        let mode = mode.parse::<Mode>().map_err(|e| error(&e))?;
+        if !plan.entries.is_empty() && mode != plan.mode {
+            return Err(error("all entries must have the same mode"));
+        }
+        plan.mode = mode;
+        let identity = match (inode.as_str(), size.as_str(), mtime.as_str()) {
+            ("", "", "") => None,
+            (inode, size, mtime) => Some(FileIdentity {
+                inode: inode.parse().map_err(|_| error(&format!("invalid inode '{}'", inode)))?,
+                size: size.parse().map_err(|_| error(&format!("invalid size '{}'", size)))?,
+                mtime: mtime.to_string(),
+            }),
+        };
+        plan.entries.push(PlanEntry {
+            source: source.clone(),
+            destination: destination.clone(),
+            replace: false,
+            identity,
+        });
+    }
+    Ok(plan)
+}
+
+/// Save Plan
+///
+/// Writes a plan to a file, to be reviewed and applied later with `load_plan`: as CSV with the
+/// columns `mode,source,destination,inode,size,mtime` if the file name ends in `.csv`, and as
+/// JSON otherwise. A CSV plan holds only the entries; its conflicts and skipped files are found
+/// again when it is applied.
+///
+pub fn save_plan(plan: &MovePlan, path: &Path) -> Result<(), String> {
+    let text = match is_csv_plan(path) {
+        true => plan_to_csv(plan),
+        false => serde_json::to_string_pretty(plan).map(|json| json + "\n").map_err(|e| e.to_string()),
+    };
+    let text = text.map_err(|e| format!("mmv: Not able to write plan {}: {}", path.to_string_lossy(), e))?;
+    fs::write(path, text)
+        .map_err(|e| format!("mmv: Not able to write plan {}: {}", path.to_string_lossy(), e))
+}
+
+/// Load Plan
+///
+/// Reads a plan written by `save_plan`, as CSV or JSON depending on its extension. The plan
+/// should be checked with `MovePlan::revalidate` before it is executed.
+///
+pub fn load_plan(path: &Path) -> Result<MovePlan, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("mmv: Not able to read plan {}: {}", path.to_string_lossy(), e))?;
+    let plan = match is_csv_plan(path) {
+        true => plan_from_csv(&text),
+        false => serde_json::from_str(&text).map_err(|e| e.to_string()),
+    };
+    plan.map_err(|e| format!("mmv: Invalid plan {}: {}", path.to_string_lossy(), e))
+}
+
+/// Plan
+///
+/// Finds the files matching the source pattern and computes their destinations without moving
+/// anything. The destination pattern is validated first, and every destination is sanitized
+/// and, with `options.portable`, checked for portability.
+///
+/// # Arguments
+///
+/// * `source_pattern` - A string representing the pattern to match source files.
+/// * `destination_pattern` - A string representing the pattern to generate destination paths.
+/// * `options` - What to do with existing destinations, whether to move or copy, filters for
+///   destination file names and whether destinations must be portable.
+/// * `tokens` - The token and filter providers available to the destination pattern.
+///
+/// # Returns
+///
+/// The plan, or all errors of all files at once if a destination could not be computed or used.
+///
+/// # Example
+///
+/// ```no
+/// let mut plan = plan("photos/*.jpg", "sorted/{mtime:%Y}/#1.jpg", &MoveOptions::default(),
+///                     &Tokens::default())?;
+/// plan.retain(|entry| !entry.source.contains("private"));
+/// execute(&plan, &mut SilentReporter)?;
+/// ```
+///
+pub fn plan(source_pattern: &str, destination_pattern: &str, options: &MoveOptions,
+            tokens: &Tokens) -> Result<MovePlan, String> {
+    let template = Template::parse(destination_pattern)?.with_name_filters(options.name_filters.clone());
+    template.validate(&build_target_path::capture_names(source_pattern)?, tokens)?;
+    let source_files = search_by_pattern::find_matching_files(source_pattern)?;
+    let root = Path::new(search_by_pattern::pattern_root(source_pattern));
+    // Tokens such as content hashes read whole files, so destinations are computed in parallel
+    let destinations: Vec<Result<Resolved, String>> = source_files.par_iter()
+        .enumerate()
+        .map(|(index, source_file_with_path)| {
+            let parts_of_new_filename = build_target_path::extract_generic_parts(
+                source_file_with_path, source_pattern)?;
+            let source_file = SourceFile {
+                path: Path::new(source_file_with_path),
+                captures: &parts_of_new_filename,
+                root,
+                counter: index + 1,
+            };
+            build_target_path::build_target_path(&source_file, &template, tokens)
+        })
+        .collect();
+    check_destinations(source_files.iter().zip(destinations), template.fixed_directory(), options)
+}
+
+/// Plan Mapping
+///
+/// Plans explicit moves given as pairs of a source and a destination path, such as a mapping
+/// read from a spreadsheet with `mapping::parse_mapping`. The destinations go through the same
+/// checks as those computed by `plan`; name filters in `options` are not applied.
+///
+/// # Arguments
+///
+/// * `pairs` - The source and the destination of every file.
+/// * `options` - What to do with existing destinations, whether to move or copy and whether
+///   destinations must be portable.
+///
+/// # Returns
+///
+/// The plan, or all errors at once if a source is missing or a destination cannot be used.
+///
+pub fn plan_mapping(pairs: &[(String, String)], options: &MoveOptions) -> Result<MovePlan, String> {
+    let destinations = pairs.iter()
+        .map(|(source, destination)| (source, Ok(Resolved::Value(destination.clone()))));
+    check_destinations(destinations, "", options)
+}
+
+/// Sanitizes the destination of a source, recording the fixed problems as warnings and the
+/// ones that cannot be fixed as errors.
+fn sanitize_destination(source: &str, destination: &str, warnings: &mut Vec<String>,
+                        errors: &mut Vec<String>) -> String {
+    let sanitized = sanitize::sanitize_path(destination);
+    for violation in &sanitized.violations {
+        let message = format!("mmv: Destination of {}: {}", source, violation);
+        if violation.is_fatal() {
+            errors.push(message);
+        } else {
+            warnings.push(message);
+        }
+    }
+    sanitized.path
+}
+
+/// Checks that the destinations of the entries are valid on Windows and macOS, except for the
+/// names in `fixed_directory`, see `portable::check_portable`.
+fn portability_errors(entries: &[PlanEntry], fixed_directory: &str) -> Vec<String> {
+    let destinations: Vec<(&str, &str)> = entries.iter()
+        .map(|entry| (entry.source.as_str(), entry.destination.as_str()))
+        .collect();
+    portable::check_portable(&destinations, fixed_directory)
+}
+
+/// Check Destinations
+///
+/// Turns the destinations computed for every source into a plan: skipped files are recorded,
+/// destinations are sanitized and checked for portability below `fixed_directory`, source
+/// identities are recorded and conflicts are resolved.
+///
+fn check_destinations<'a, I>(destinations: I, fixed_directory: &str,
+                             options: &MoveOptions) -> Result<MovePlan, String>
+    where I: Iterator<Item = (&'a String, Result<Resolved, String>)> {
+    // Every problem with the computed destinations is reported before the first file is moved
+    let mut errors = Vec::new();
+    let mut plan = MovePlan { mode: options.mode, ..MovePlan::default() };
+    for (source_file_with_path, destination) in destinations {
+        let destination_path_filename = match destination {
+            Ok(Resolved::Value(path)) => path,
+            Ok(Resolved::Skip(reason)) => {
+                plan.skipped.push(Skipped { source: source_file_with_path.clone(), reason });
+                continue;
+            }
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        let destination = sanitize_destination(source_file_with_path, &destination_path_filename,
+                                               &mut plan.warnings, &mut errors);
+        match FileIdentity::of(Path::new(source_file_with_path)) {
+            Ok(identity) => plan.entries.push(PlanEntry {
+                source: source_file_with_path.clone(),
+                destination,
+                replace: false,
+                identity: Some(identity),
+            }),
+            Err(e) => errors.push(e),
+        }
+    }
+    if options.portable {
+        errors.extend(portability_errors(&plan.entries, fixed_directory));
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    resolve_conflicts(&mut plan, options.on_conflict);
+    Ok(plan)
+}
+
+/// Preview
+///
+/// Reports the warnings, the skipped files and the entries of a plan to `reporter` without
+/// moving anything, as for a dry run. If the plan has conflicts, every entry involved in one is
+/// reported as conflicting instead.
+///
+/// # Returns
+///
+/// An error listing all conflicts, if the plan has any.
+///
+pub fn preview(plan: &MovePlan, reporter: &mut dyn Reporter) -> Result<(), String> {
+    for warning in &plan.warnings {
+        reporter.warning(warning);
+    }
+    for skipped in &plan.skipped {
+        reporter.skipped(skipped);
+    }
+    if !plan.conflicts.is_empty() {
+        let conflicts: Vec<String> = plan.conflicts.iter().map(|conflict| conflict.to_string()).collect();
+        for entry in &plan.entries {
+            for (conflict, message) in plan.conflicts.iter().zip(&conflicts) {
+                if conflict.involves(&entry.source) {
+                    reporter.conflict(entry, message);
+                }
+            }
+        }
+        return Err(conflicts.join("\n"));
+    }
+    for entry in &plan.entries {
+        reporter.planned(entry);
+    }
+    Ok(())
+}
+
+/// Execute
+///
+/// Carries out a plan: replaces the destinations marked for replacement, creates missing parent
+/// directories and moves or copies every file, reporting every step to `reporter`. Nothing is
+/// moved if the plan has conflicts, or if a destination not marked for replacement exists by now.
+///
+/// # Arguments
+///
+/// * `plan` - The plan computed by `plan`, possibly filtered or loaded from a file.
+/// * `reporter` - Receives the warnings and skipped files of the plan and the progress of the
+///   moves, e.g. `PrintReporter` to print `source -> destination` for every moved file.
+///
+pub fn execute(plan: &MovePlan, reporter: &mut dyn Reporter) -> Result<(), String> {
+    preview(plan, reporter)?;
+    for entry in &plan.entries {
+        let destination_path = Path::new(&entry.destination);
+        if destination_path.exists() {
+            let error = match entry.replace {
+                false => format!("mmv: Not able to replace existing file: {}", entry.destination),
+                true if std::fs::remove_file(destination_path).is_err() =>
+                    "mmv: Not able to replace existing file".to_string(),
+                true => continue,
+            };
+            reporter.failed(entry, &error);
+            return Err(error);
+        }
+    }
+    for entry in &plan.entries {
+        reporter.started(entry);
+        match move_file(entry, plan.mode) {
+            Ok(_) => reporter.completed(entry),
+            Err(e) => {
+                reporter.failed(entry, &e);
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves or copies the file of one plan entry, creating the parent directories of its destination.
+fn move_file(entry: &PlanEntry, mode: Mode) -> Result<(), String> {
+    let destination_path = Path::new(&entry.destination);
+    if let Some(parent) = destination_path.parent() {
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("mmv: Not able to create directory {}: {}", parent.to_string_lossy(), e))?;
+        }
+    }
+    let result = match mode {
+        Mode::Move => std::fs::rename(Path::new(&entry.source), destination_path),
+        Mode::Copy => std::fs::copy(Path::new(&entry.source), destination_path).map(|_| ()),
+    };
+    result.map_err(|e| format!("Error: {}", e))
+}
+
+
+#[test]
+fn test_plan_conflicts() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    for name in ["a_1.txt", "b_1.txt", "c_2.txt", "2.txt"] {
+        std::fs::write(temp_dir.path().join(name), "").expect("Failed to write file");
+    }
+    let source = temp_dir.path().join("*_*.txt").to_string_lossy().to_string();
+    let destination = temp_dir.path().join("#2.txt").to_string_lossy().to_string();
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+
+    let mut plan = plan(&source, &destination, &MoveOptions::default(), &Tokens::default()).unwrap();
+    assert_eq!(plan.entries.len(), 3);
+    assert_eq!(plan.conflicts, vec![
+        Conflict::Exists { source: path("c_2.txt"), destination: path("2.txt") },
+        Conflict::Duplicate { sources: vec![path("a_1.txt"), path("b_1.txt")], destination: path("1.txt") },
+    ]);
+    assert!(execute(&plan, &mut crate::reporter::SilentReporter).unwrap_err().ends_with(&format!("would all be moved to {}", path("1.txt"))));
+    assert!(Path::new(&path("a_1.txt")).exists());
+
+    plan.retain(|entry| !entry.source.ends_with("b_1.txt") && !entry.source.ends_with("c_2.txt"));
+    assert!(plan.conflicts.is_empty());
+    execute(&plan, &mut crate::reporter::SilentReporter).unwrap();
+    assert!(Path::new(&path("1.txt")).exists());
+    assert!(Path::new(&path("b_1.txt")).exists());
+}
+
+#[test]
+fn test_plan_on_conflict() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    std::fs::create_dir_all(path("in")).expect("Failed to create parent directories");
+    for name in ["in/a.txt", "in/b.txt", "b.txt"] {
+        std::fs::write(path(name), name).expect("Failed to write file");
+    }
+    let options = |on_conflict: OnConflict| MoveOptions { on_conflict, mode: Mode::Copy, ..MoveOptions::default() };
+
+    let skipping = plan(&path("in/*.txt"), &path("#1.txt"), &options(OnConflict::Skip), &Tokens::default()).unwrap();
+    assert_eq!(skipping.entries.len(), 1);
+    assert_eq!((skipping.entries[0].source.clone(), skipping.entries[0].destination.clone()), (path("in/a.txt"), path("a.txt")));
+    assert_eq!(skipping.skipped, vec![Skipped { source: path("in/b.txt"), reason: format!("{} already exists", path("b.txt")) }]);
+
+    let overwriting = plan(&path("in/*.txt"), &path("#1.txt"), &options(OnConflict::Overwrite), &Tokens::default()).unwrap();
+    assert!(overwriting.conflicts.is_empty() && overwriting.entries[1].replace);
+    execute(&overwriting, &mut crate::reporter::SilentReporter).unwrap();
+    assert_eq!(std::fs::read_to_string(path("b.txt")).unwrap(), "in/b.txt");
+    assert!(Path::new(&path("in/b.txt")).exists());
+}
+
+#[test]
+fn test_plan_mapping() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    for name in ["a", "b", "c", "x", "y"] {
+        std::fs::write(path(name), name).expect("Failed to write file");
+    }
+    let pairs: Vec<(String, String)> = [("b", "c"), ("x", "y"), ("c", "a"), ("y", "z"), ("a", "b")].iter()
+        .map(|(source, destination)| (path(source), path(destination)))
+        .collect();
+
+    let mut planned = plan_mapping(&pairs, &MoveOptions::default()).unwrap();
+    assert_eq!(planned.conflicts, vec![
+        Conflict::Cycle { sources: vec![path("a"), path("b"), path("c")] },
+        Conflict::Chain { source: path("x"), destination: path("y") },
+    ]);
+    assert_eq!(planned.conflicts[0].to_string(),
+               format!("mmv: {} -> {} -> {} -> {} form a cycle", path("a"), path("b"), path("c"), path("a")));
+
+    planned.retain(|entry| entry.source != path("c") && entry.source != path("y"));
+    assert_eq!(planned.conflicts, vec![Conflict::Chain { source: path("a"), destination: path("b") }]);
+
+    let missing = vec![(path("missing"), path("found"))];
+    assert!(plan_mapping(&missing, &MoveOptions::default()).unwrap_err()
+        .starts_with(&format!("mmv: Not able to read {}", path("missing"))));
+}
+
+#[test]
+fn test_plan_serialization() {
+    let plan = MovePlan {
+        mode: Mode::Copy,
+        entries: vec![PlanEntry { source: "a.txt".to_string(), destination: "b.txt".to_string(), replace: false,
+                                    identity: None }],
+        conflicts: vec![Conflict::Exists { source: "a.txt".to_string(), destination: "b.txt".to_string() }],
+        skipped: vec![Skipped { source: "c.txt".to_string(), reason: "no tag".to_string() }],
+        warnings: vec![],
+    };
+    let json = serde_json::to_string(&plan).unwrap();
+    assert!(json.starts_with(r#"{"mode":"copy","entries":"#));
+    assert!(json.contains(r#""conflicts":[{"kind":"exists","source":"a.txt","destination":"b.txt"}]"#));
+    assert_eq!(serde_json::from_str::<MovePlan>(&json).unwrap(), plan);
+}
+
+#[test]
+fn test_stored_plan() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    for name in ["a_1.txt", "b_2.txt"] {
+        std::fs::write(path(name), name).expect("Failed to write file");
+    }
+    let planned = plan(&path("*_*.txt"), &path("#2.txt"), &MoveOptions::default(), &Tokens::default()).unwrap();
+    let identity = planned.entries[0].identity.clone().unwrap();
+    assert_eq!(identity.size, 7);
+    assert!(identity.mtime.ends_with('Z'));
+    save_plan(&planned, Path::new(&path("plan.json"))).unwrap();
+
+    let mut loaded = load_plan(Path::new(&path("plan.json"))).unwrap();
+    assert_eq!(loaded, planned);
+    std::fs::write(path("1.txt"), "").expect("Failed to write file");
+    loaded.revalidate(&MoveOptions { on_conflict: OnConflict::Skip, ..MoveOptions::default() }).unwrap();
+    assert_eq!(loaded.entries.len(), 1);
+    assert_eq!(loaded.skipped[0].source, path("a_1.txt"));
+
+    std::fs::write(path("b_2.txt"), "changed!").expect("Failed to write file");
+    let error = load_plan(Path::new(&path("plan.json"))).unwrap().revalidate(&MoveOptions::default()).unwrap_err();
+    assert!(error.starts_with(&format!("mmv: {} has changed since it was planned: size 7 is now 8", path("b_2.txt"))));
+    std::fs::remove_file(path("a_1.txt")).expect("Failed to remove file");
+    assert!(load_plan(Path::new(&path("plan.json"))).unwrap().revalidate(&MoveOptions::default()).unwrap_err()
+        .starts_with(&format!("mmv: Not able to read {}", path("a_1.txt"))));
+    assert!(load_plan(Path::new(&path("a_1.txt"))).is_err());
+
+    let mut unchecked = load_plan(Path::new(&path("plan.json"))).unwrap();
+    unchecked.entries.retain(|entry| entry.source == path("b_2.txt"));
+    unchecked.entries[0].identity = None;
+    assert!(unchecked.revalidate(&MoveOptions::default()).unwrap_err()
+        .starts_with(&format!("mmv: {} has no identity in the plan", path("b_2.txt"))));
+}
+
+#[test]
+fn test_stored_plan_csv() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    for name in ["a,1.txt", "b\"2.txt"] {
+        std::fs::write(path(name), name).expect("Failed to write file");
+    }
+    let options = MoveOptions { mode: Mode::Copy, ..MoveOptions::default() };
+    let planned = plan(&path("*.txt"), &path("out/#1.txt"), &options, &Tokens::default()).unwrap();
+    save_plan(&planned, Path::new(&path("plan.csv"))).unwrap();
+    let csv = std::fs::read_to_string(path("plan.csv")).unwrap();
+    assert!(csv.starts_with("mode,source,destination,inode,size,mtime\ncopy,\""));
+    assert!(csv.contains("b\"\"2.txt"));
+
+    let mut loaded = load_plan(Path::new(&path("plan.csv"))).unwrap();
+    assert_eq!(loaded, planned);
+    loaded.revalidate(&MoveOptions::default()).unwrap();
+
+    std::fs::write(path("edited.csv"), format!("mode,source,destination,inode,size,mtime\nmove,{},x.txt,,,\n",
+                                               path("a,1.txt").replace(',', "_"))).unwrap();
+    assert!(load_plan(Path::new(&path("edited.csv"))).unwrap().revalidate(&MoveOptions::default()).unwrap_err()
+        .contains("has no identity in the plan"));
+
+    // Destinations edited in a spreadsheet go through the same checks as planned ones
+    let edit = |destination: &str| {
+        let edited = csv.replace(&format!("{},", csv_field(&path("out/b\"2.txt"))),
+                                 &format!("{},", csv_field(&path(destination))));
+        assert_ne!(edited, csv);
+        std::fs::write(path("edited.csv"), edited).unwrap();
+        load_plan(Path::new(&path("edited.csv"))).unwrap()
+    };
+    let mut edited = edit("out/b\u{7}.txt");
+    edited.revalidate(&MoveOptions::default()).unwrap();
+    assert_eq!(edited.entries[1].destination, path("out/b_.txt"));
+    assert_eq!(edited.warnings.len(), 1);
+    assert!(edit("out//b.txt").revalidate(&MoveOptions::default()).unwrap_err().ends_with("is empty"));
+    assert!(edit(&"b/".repeat(3000)).revalidate(&MoveOptions::default()).unwrap_err().contains("bytes long"));
+    let portable = MoveOptions { portable: true, ..MoveOptions::default() };
+    edit("out/con.txt").revalidate(&MoveOptions::default()).unwrap();
+    assert!(edit("out/con.txt").revalidate(&portable).unwrap_err().contains("'con.txt' is a reserved name"));
+
+    std::fs::write(path("minimal.json"), format!(r#"{{"entries": [{{"source": "{}", "destination": "x.txt"}}]}}"#,
+                                                  path("a,1.txt"))).unwrap();
+    let minimal = load_plan(Path::new(&path("minimal.json"))).unwrap();
+    assert_eq!((minimal.mode, minimal.entries.len()), (Mode::Move, 1));
+    std::fs::write(path("bad.csv"), "source,destination\na,b\n").unwrap();
+    assert!(load_plan(Path::new(&path("bad.csv"))).unwrap_err().contains("expected the header"));
+    std::fs::write(path("bad.csv"), format!("{}\nmove,a,b,1,x,t\n", PLAN_CSV_HEADER)).unwrap();
+    assert!(load_plan(Path::new(&path("bad.csv"))).unwrap_err().ends_with("line 2: invalid size 'x'"));
+}
+
+#[test]
+fn test_execute_reports_events() {
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Reporter for Recorder {
+        fn skipped(&mut self, skipped: &Skipped) {
+            self.0.push(format!("skipped {}", skipped.source));
+        }
+
+        fn planned(&mut self, entry: &PlanEntry) {
+            self.0.push(format!("planned {}", entry.source));
+        }
+
+        fn started(&mut self, entry: &PlanEntry) {
+            self.0.push(format!("started {}", entry.source));
+        }
+
+        fn completed(&mut self, entry: &PlanEntry) {
+            self.0.push(format!("completed {}", entry.source));
+        }
+
+        fn failed(&mut self, entry: &PlanEntry, _error: &str) {
+            self.0.push(format!("failed {}", entry.source));
+        }
+    }
+
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    std::fs::write(path("a.txt"), "").expect("Failed to write file");
+    let entry = |source: &str| PlanEntry {
+        source: path(source),
+        destination: path("out/b.txt"),
+        replace: false,
+        identity: None,
+    };
+    let plan = MovePlan {
+        entries: vec![entry("a.txt"), entry("missing.txt")],
+        skipped: vec![Skipped { source: "c.txt".to_string(), reason: "no tag".to_string() }],
+        ..MovePlan::default()
+    };
+    let mut recorder = Recorder::default();
+
+    assert!(execute(&plan, &mut recorder).is_err());
+
+    let a = path("a.txt");
+    let missing = path("missing.txt");
+    assert_eq!(recorder.0, vec![
+        "skipped c.txt".to_string(),
+        format!("planned {}", a), format!("planned {}", missing),
+        format!("started {}", a), format!("completed {}", a),
+        format!("started {}", missing), format!("failed {}", missing),
+    ]);
+    assert!(Path::new(&path("out/b.txt")).exists());
+}
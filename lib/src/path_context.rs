@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::template::{SourceFile, Token};
+use crate::tokens::{Resolved, TokenProvider};
+
+/// Path Context Provider
+///
+/// Resolves tokens from the location of a source file:
+///
+/// * `{parent}` - name of the directory containing the file.
+/// * `{dir:N}` - name of the N-th directory above the file: `{dir:1}` is the parent,
+///   `{dir:2}` the grandparent.
+/// * `{relpath}` - directory of the file relative to the pattern root, e.g. `2023/trip` for
+///   `photos/2023/trip/a.jpg` matched by `photos/**/*.jpg`; empty for files directly in the root.
+///   `{relpath:_}` joins the directories with `_` instead of `/`, to flatten a tree.
+/// * `{depth}` - number of directories between the pattern root and the file.
+///
+pub struct PathContextProvider;
+
+/// Relative Directories
+///
+/// Returns the names of the directories between the pattern root and the file.
+///
+fn relative_directories(file: &SourceFile) -> Vec<String> {
+    let parent = file.path.parent().unwrap_or(Path::new(""));
+    parent.strip_prefix(file.root)
+        .unwrap_or(parent)
+        .iter()
+        .map(|component| component.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Ancestor Name
+///
+/// Returns the name of the `level`-th directory above a file, resolving relative paths such as
+/// `./a.txt` against the current directory.
+///
+fn ancestor_name(path: &Path, level: usize) -> Result<String, String> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let parent = fs::canonicalize(parent).map_err(|e| e.to_string())?;
+    parent.ancestors()
+        .nth(level - 1)
+        .and_then(|ancestor| ancestor.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| format!("there is no directory {} level(s) above the file", level))
+}
+
+fn directory_level(token: &Token) -> Result<usize, String> {
+    match token.argument.as_deref() {
+        None => Ok(1),
+        Some(argument) => match argument.parse::<usize>() {
+            Ok(level) if level >= 1 => Ok(level),
+            _ => Err(format!("invalid directory level '{}', expected a number from 1", argument)),
+        },
+    }
+}
+
+impl TokenProvider for PathContextProvider {
+    fn provides(&self, name: &str) -> bool {
+        matches!(name, "parent" | "dir" | "relpath" | "depth")
+    }
+
+    fn validate(&self, token: &Token) -> Result<(), String> {
+        match (token.name.as_str(), &token.argument) {
+            ("dir", _) => directory_level(token).map(|_| ()),
+            ("relpath", _) | (_, None) => Ok(()),
+            (name, Some(_)) => Err(format!("token '{}' takes no argument", name)),
+        }
+    }
+
+    fn resolve(&self, token: &Token, file: &SourceFile) -> Result<Resolved, String> {
+        let value = match token.name.as_str() {
+            "parent" => ancestor_name(file.path, 1)?,
+            "dir" => ancestor_name(file.path, directory_level(token)?)?,
+            "relpath" => relative_directories(file).join(token.argument.as_deref().unwrap_or("/")),
+            _ => relative_directories(file).len().to_string(),
+        };
+        Ok(Resolved::Value(value))
+    }
+}
+
+
+#[test]
+fn test_resolve_path_context_tokens() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let root = temp_dir.path().join("photos");
+    let path = root.join("2023/trip/a.jpg");
+    fs::create_dir_all(path.parent().unwrap()).expect("Failed to create parent directories");
+    fs::File::create(&path).expect("Failed to create file");
+    let file = SourceFile { root: &root, ..SourceFile::new(&path) };
+    let value = |token: Token| match PathContextProvider.resolve(&token, &file).unwrap() {
+        Resolved::Value(value) => value,
+        Resolved::Skip(reason) => panic!("unexpected skip: {}", reason),
+    };
+
+    assert_eq!(value(Token::new("parent", None)), "trip");
+    assert_eq!(value(Token::new("dir", Some("2"))), "2023");
+    assert_eq!(value(Token::new("dir", Some("3"))), "photos");
+    assert_eq!(value(Token::new("relpath", None)), "2023/trip");
+    assert_eq!(value(Token::new("relpath", Some("_"))), "2023_trip");
+    assert_eq!(value(Token::new("depth", None)), "2");
+
+    let file = SourceFile::new(&path);
+    assert_eq!(PathContextProvider.resolve(&Token::new("relpath", None), &file).unwrap(),
+               Resolved::Value(String::new()));
+}
+
+#[test]
+fn test_validate_path_context_tokens() {
+    assert!(PathContextProvider.validate(&Token::new("dir", Some("2"))).is_ok());
+    assert!(PathContextProvider.validate(&Token::new("dir", Some("0"))).is_err());
+    assert!(PathContextProvider.validate(&Token::new("parent", Some("2"))).is_err());
+    assert!(PathContextProvider.validate(&Token::new("relpath", Some("-"))).is_ok());
+}
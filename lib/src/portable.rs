@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+/// Names Windows reserves for devices, with or without an extension, including the ports
+/// numbered with superscript digits.
+const RESERVED_NAMES: [&str; 30] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM0", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "COM¹", "COM²", "COM³",
+    "LPT0", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9", "LPT¹", "LPT²", "LPT³",
+];
+
+/// Characters Windows does not allow in file names.
+const FORBIDDEN_CHARACTERS: [char; 9] = ['<', '>', ':', '"', '\\', '|', '?', '*', '/'];
+
+/// Name Problems
+///
+/// Lists the reasons a single path component cannot be used on Windows or macOS.
+///
+fn name_problems(name: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let stem = name.split('.').next().unwrap_or(name);
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end())) {
+        problems.push(format!("'{}' is a reserved name on Windows", name));
+    }
+    let forbidden: String = name.chars()
+        .filter(|c| FORBIDDEN_CHARACTERS.contains(c) || c.is_control())
+        .collect();
+    if !forbidden.is_empty() {
+        problems.push(format!("'{}' contains characters forbidden on Windows: {:?}", name, forbidden));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        problems.push(format!("'{}' ends with a dot or a space", name));
+    }
+    problems
+}
+
+/// Portability Problems
+///
+/// Lists the reasons a destination path cannot be unpacked on Windows or macOS: reserved
+/// device names such as `CON` or `nul.txt`, characters such as `:` or `?`, and names ending
+/// with a dot or a space. The `.` and `..` components are allowed.
+///
+pub fn portability_problems(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|component| !matches!(*component, "" | "." | ".."))
+        .flat_map(name_problems)
+        .collect()
+}
+
+/// Check Portable
+///
+/// Checks a whole set of destinations for portability: every path on its own with
+/// `portability_problems`, and all of them together for paths, including directories, that
+/// differ only in case and would be the same file on case-insensitive file systems.
+///
+/// # Arguments
+///
+/// * `destinations` - Pairs of a source path and its computed destination path.
+/// * `fixed_directory` - The directory every destination is rendered in, such as the part of
+///   the destination pattern before its first marker or token, ending with `/`. Its names are
+///   not checked on their own, as the pattern does not produce them; empty to check everything.
+///
+/// # Returns
+///
+/// An error message for every problem found, empty if all destinations are portable.
+///
+pub fn check_portable(destinations: &[(&str, &str)], fixed_directory: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    // The first spelling of every path and directory, by its lowercase form
+    let mut spellings: HashMap<String, &str> = HashMap::new();
+    for (source, destination) in destinations {
+        let rendered = destination.strip_prefix(fixed_directory).unwrap_or(destination);
+        for problem in portability_problems(rendered) {
+            errors.push(format!("mmv: Destination {} of {} is not portable: {}",
+                                destination, source, problem));
+        }
+        let prefixes = destination.match_indices('/')
+            .map(|(slash, _)| &destination[..slash])
+            .filter(|prefix| !prefix.is_empty())
+            .chain(std::iter::once(*destination));
+        for prefix in prefixes {
+            let spelling = spellings.entry(prefix.to_lowercase()).or_insert(prefix);
+            if *spelling != prefix {
+                errors.push(format!("mmv: Destinations {} and {} differ only in case", spelling,
+                                    prefix));
+                // Report every pair of spellings once
+                *spelling = prefix;
+            }
+        }
+    }
+    errors
+}
+
+
+#[test]
+fn test_portability_problems() {
+    assert!(portability_problems("out/../photos/a.jpg").is_empty());
+    assert_eq!(portability_problems("out/con.txt"),
+               vec!["'con.txt' is a reserved name on Windows"]);
+    assert_eq!(portability_problems("NUL/LPT1"), vec!["'NUL' is a reserved name on Windows",
+                                                      "'LPT1' is a reserved name on Windows"]);
+    assert!(portability_problems("console.txt").is_empty());
+    assert_eq!(portability_problems("lpt0.log/COM³"), vec!["'lpt0.log' is a reserved name on Windows",
+                                                          "'COM³' is a reserved name on Windows"]);
+    assert!(portability_problems("COM10").is_empty());
+    assert_eq!(portability_problems("a:b?.txt"),
+               vec!["'a:b?.txt' contains characters forbidden on Windows: \":?\""]);
+    assert_eq!(portability_problems("dir./name "), vec!["'dir.' ends with a dot or a space",
+                                                        "'name ' ends with a dot or a space"]);
+}
+
+#[test]
+fn test_check_portable() {
+    assert!(check_portable(&[("a", "out/a.txt"), ("b", "out/b.txt")], "").is_empty());
+    assert_eq!(check_portable(&[("a", "out/A.txt"), ("b", "out/a.txt")], ""),
+               vec!["mmv: Destinations out/A.txt and out/a.txt differ only in case"]);
+    assert_eq!(check_portable(&[("a", "Out/a.txt"), ("b", "out/b.txt"), ("c", "out/c.txt")], ""),
+               vec!["mmv: Destinations Out and out differ only in case"]);
+    assert_eq!(check_portable(&[("src/aux", "aux")], ""),
+               vec!["mmv: Destination aux of src/aux is not portable: 'aux' is a reserved name on Windows"]);
+    assert!(check_portable(&[("a", "/mnt/c/Users/x:y/a.txt")], "/mnt/c/Users/x:y/").is_empty());
+    assert_eq!(check_portable(&[("a", "/mnt/x:y/a?.txt")], "/mnt/x:y/").len(), 1);
+}
@@ -0,0 +1,264 @@
+use crate::move_plan::PlanEntry;
+use std::str::FromStr;
+
+/// How the paths of a report are quoted, as with `ls --quoting-style`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuotingStyle {
+    /// Paths as they are.
+    #[default]
+    Literal,
+    /// Paths that can be pasted into a POSIX shell: single-quoted when they contain anything
+    /// but letters, digits and `_-./+,:@%=^`, with control characters as `$'\n'`.
+    Shell,
+    /// Paths as double-quoted C strings, with `\"`, `\\`, `\n` and the like, and other control
+    /// characters as octal escapes.
+    C,
+}
+
+impl FromStr for QuotingStyle {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<QuotingStyle, String> {
+        match value {
+            "literal" => Ok(QuotingStyle::Literal),
+            "shell" => Ok(QuotingStyle::Shell),
+            "c" => Ok(QuotingStyle::C),
+            _ => Err(format!("mmv: Unknown quoting style '{}', expected 'literal', 'shell' or 'c'", value)),
+        }
+    }
+}
+
+/// Escapes a character the way C and `$'...'` strings of the shell write it.
+fn escape_char(c: char, escaped: &mut String) {
+    match c {
+        '\\' => escaped.push_str("\\\\"),
+        '\x07' => escaped.push_str("\\a"),
+        '\x08' => escaped.push_str("\\b"),
+        '\x0c' => escaped.push_str("\\f"),
+        '\n' => escaped.push_str("\\n"),
+        '\r' => escaped.push_str("\\r"),
+        '\t' => escaped.push_str("\\t"),
+        '\x0b' => escaped.push_str("\\v"),
+        c if c.is_control() => {
+            for byte in c.to_string().bytes() {
+                escaped.push_str(&format!("\\{:03o}", byte));
+            }
+        }
+        c => escaped.push(c),
+    }
+}
+
+/// Quote
+///
+/// Quotes a path in the given style, so that names with spaces, quotes or control characters
+/// can be told apart in a report and pasted back into a shell.
+///
+/// # Example
+///
+/// ```no
+/// assert_eq!(quote("it's.txt", QuotingStyle::Shell), "'it'\\''s.txt'");
+/// assert_eq!(quote("a\tb.txt", QuotingStyle::C), "\"a\\tb.txt\"");
+/// ```
+///
+pub fn quote(path: &str, style: QuotingStyle) -> String {
+    match style {
+        QuotingStyle::Literal => path.to_string(),
+        QuotingStyle::Shell => {
+            let safe = |c: char| c.is_alphanumeric() || "_-./+,:@%=^".contains(c);
+            if !path.is_empty() && path.chars().all(safe) {
+                return path.to_string();
+            }
+            let mut quoted = String::from("'");
+            for c in path.chars() {
+                match c {
+                    '\'' => quoted.push_str("'\\''"),
+                    c if c.is_control() => {
+                        quoted.push_str("'$'");
+                        escape_char(c, &mut quoted);
+                        quoted.push_str("''");
+                    }
+                    c => quoted.push(c),
+                }
+            }
+            quoted.push('\'');
+            // Quotes left empty by a leading or trailing quote or control character
+            let quoted = match quoted.len() > 2 {
+                true => quoted.strip_prefix("''").unwrap_or(&quoted).to_string(),
+                false => quoted,
+            };
+            match quoted.len() > 2 {
+                true => quoted.strip_suffix("''").unwrap_or(&quoted).to_string(),
+                false => quoted,
+            }
+        }
+        QuotingStyle::C => {
+            let mut quoted = String::from("\"");
+            for c in path.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    c => escape_char(c, &mut quoted),
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+    }
+}
+
+/// A part of a report format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Source,
+    Destination,
+    Size,
+}
+
+/// Report Format
+///
+/// What `PrintReporter` prints for every file, e.g. `{src}\t{dst}\t{size}`: `{src}` and
+/// `{dst}` are the source and the destination quoted in the quoting style, `{size}` is the size
+/// of the file in bytes, `{{` and `}}` are braces, and `\t`, `\n`, `\0` and `\\` are a tab, a
+/// line break, a NUL character and a backslash. Every line ends with a line break.
+///
+/// # Example
+///
+/// ```no
+/// let format = ReportFormat::parse("{src}\\t{dst}\\t{size}", QuotingStyle::Shell)?;
+/// execute(&plan, &mut PrintReporter::new(false).with_format(format))?;
+/// ```
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportFormat {
+    parts: Vec<Part>,
+    quoting: QuotingStyle,
+}
+
+impl Default for ReportFormat {
+    /// `{src} -> {dst}`, the report of `mmv`.
+    fn default() -> ReportFormat {
+        ReportFormat {
+            parts: vec![Part::Source, Part::Text(" -> ".to_string()), Part::Destination],
+            quoting: QuotingStyle::Literal,
+        }
+    }
+}
+
+impl ReportFormat {
+    /// Parses a format, or returns an error naming an unknown or unclosed field.
+    pub fn parse(format: &str, quoting: QuotingStyle) -> Result<ReportFormat, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some('0') => text.push('\0'),
+                    Some('\\') => text.push('\\'),
+                    Some(other) => {
+                        text.push('\\');
+                        text.push(other);
+                    }
+                    None => text.push('\\'),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err(format!("mmv: Unclosed field in format '{}'", format));
+                    };
+                    let part = match &rest[..end] {
+                        "src" => Part::Source,
+                        "dst" => Part::Destination,
+                        "size" => Part::Size,
+                        field => return Err(format!("mmv: Unknown field '{{{}}}' in format, expected {{src}}, \
+                                                     {{dst}} or {{size}}", field)),
+                    };
+                    chars = rest[end + 1..].chars();
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(ReportFormat { parts, quoting })
+    }
+
+    /// Renders the line of a file, without the line break. The size is the one recorded when
+    /// the file was planned, or else the size of the source or the destination, whichever
+    /// exists; it is left empty if neither does.
+    pub fn render(&self, entry: &PlanEntry) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => line.push_str(text),
+                Part::Source => line.push_str(&quote(&entry.source, self.quoting)),
+                Part::Destination => line.push_str(&quote(&entry.destination, self.quoting)),
+                Part::Size => {
+                    let size = entry.identity.as_ref().map(|identity| identity.size).or_else(|| {
+                        std::fs::metadata(&entry.source)
+                            .or_else(|_| std::fs::metadata(&entry.destination))
+                            .map(|metadata| metadata.len())
+                            .ok()
+                    });
+                    if let Some(size) = size {
+                        line.push_str(&size.to_string());
+                    }
+                }
+            }
+        }
+        line
+    }
+}
+
+
+#[test]
+fn test_quote() {
+    assert_eq!(quote("photos/a_1.jpg", QuotingStyle::Shell), "photos/a_1.jpg");
+    assert_eq!(quote("my photo.jpg", QuotingStyle::Shell), "'my photo.jpg'");
+    assert_eq!(quote("it's.txt", QuotingStyle::Shell), "'it'\\''s.txt'");
+    assert_eq!(quote("'a'", QuotingStyle::Shell), "\\''a'\\'");
+    assert_eq!(quote("a\nb", QuotingStyle::Shell), "'a'$'\\n''b'");
+    assert_eq!(quote("", QuotingStyle::Shell), "''");
+    assert_eq!(quote("фото 1.jpg", QuotingStyle::Shell), "'фото 1.jpg'");
+
+    assert_eq!(quote("say \"hi\"\\\t\x1b.txt", QuotingStyle::C), "\"say \\\"hi\\\"\\\\\\t\\033.txt\"");
+    assert_eq!(quote("a\nb $x", QuotingStyle::Literal), "a\nb $x");
+    assert_eq!("c".parse::<QuotingStyle>().unwrap(), QuotingStyle::C);
+    assert!("escape".parse::<QuotingStyle>().is_err());
+}
+
+#[test]
+fn test_report_format() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let source = temp_dir.path().join("my file.txt").to_string_lossy().to_string();
+    std::fs::write(&source, "12345").expect("Failed to write file");
+    let entry = PlanEntry {
+        source: source.clone(),
+        destination: "out/it's.txt".to_string(),
+        replace: false,
+        identity: None,
+    };
+
+    let format = ReportFormat::parse("{src}\\t{dst}\\t{size}", QuotingStyle::Shell).unwrap();
+    assert_eq!(format.render(&entry), format!("'{}'\t'out/it'\\''s.txt'\t5", source));
+    assert_eq!(ReportFormat::default().render(&entry), format!("{} -> out/it's.txt", source));
+    assert_eq!(ReportFormat::parse("{{{dst}}} \\x", QuotingStyle::C).unwrap().render(&entry),
+               "{\"out/it's.txt\"} \\x");
+    assert!(ReportFormat::parse("{src", QuotingStyle::Literal).unwrap_err().starts_with("mmv: Unclosed field"));
+    assert!(ReportFormat::parse("{name}", QuotingStyle::Literal).unwrap_err().contains("'{name}'"));
+}
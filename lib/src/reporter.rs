@@ -0,0 +1,257 @@
+use crate::move_plan::{PlanEntry, Skipped};
+use crate::report_format::ReportFormat;
+use serde::Serialize;
+use std::io::{self, Stdout, Write};
+
+/// Reporter
+///
+/// Receives the events of a mass move as `move_plan::execute` carries out a plan, so that a
+/// caller decides how to show progress: the command line prints it with `PrintReporter`, a GUI
+/// or a service can collect or forward it instead. All events are ignored by default.
+///
+/// Events arrive in this order: `warning` and `skipped` for the problems found while planning,
+/// `conflict` for every entry involved in a conflict, which stops the run, or else `planned`
+/// for every entry of the plan, then `started` and `completed` for every move, or `failed` for
+/// the move that stopped the run. `error` is left to the caller, for the error of the run.
+///
+pub trait Reporter {
+    /// A destination was fixed while planning, e.g. a name was truncated.
+    fn warning(&mut self, _message: &str) {}
+
+    /// A file is left where it is.
+    fn skipped(&mut self, _skipped: &Skipped) {}
+
+    /// A file cannot be moved because of `error`, a conflict with the destination or another file.
+    fn conflict(&mut self, _entry: &PlanEntry, _error: &str) {}
+
+    /// A file is going to be moved.
+    fn planned(&mut self, _entry: &PlanEntry) {}
+
+    /// A file is being moved.
+    fn started(&mut self, _entry: &PlanEntry) {}
+
+    /// A file was moved.
+    fn completed(&mut self, _entry: &PlanEntry) {}
+
+    /// A file could not be moved, and the run stops with `error`.
+    fn failed(&mut self, _entry: &PlanEntry, _error: &str) {}
+
+    /// The run stopped with `error`, e.g. because no file matched the pattern.
+    fn error(&mut self, _error: &str) {}
+}
+
+/// A reporter that ignores all events.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {}
+
+/// Print Reporter
+///
+/// The reporter of the `mmv` command: prints `source -> destination`, or a line in another
+/// `ReportFormat`, to stdout for every moved file, or for every planned one in a dry run, and
+/// warnings and skipped files to stderr. Failures are left to the caller, which receives them
+/// as the error of the run.
+///
+#[derive(Debug, Clone, Default)]
+pub struct PrintReporter {
+    pub dry_run: bool,
+    pub format: ReportFormat,
+}
+
+impl PrintReporter {
+    pub fn new(dry_run: bool) -> PrintReporter {
+        PrintReporter { dry_run, ..PrintReporter::default() }
+    }
+
+    /// Prints every file as `format` instead of `source -> destination`.
+    pub fn with_format(mut self, format: ReportFormat) -> PrintReporter {
+        self.format = format;
+        self
+    }
+}
+
+impl Reporter for PrintReporter {
+    fn warning(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn skipped(&mut self, skipped: &Skipped) {
+        eprintln!("mmv: Skipping {}: {}", skipped.source, skipped.reason);
+    }
+
+    fn planned(&mut self, entry: &PlanEntry) {
+        if self.dry_run {
+            println!("{}", self.format.render(entry));
+        }
+    }
+
+    fn completed(&mut self, entry: &PlanEntry) {
+        println!("{}", self.format.render(entry));
+    }
+}
+
+/// How `RecordReporter` writes its records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordFormat {
+    /// A JSON object with the `status`, `source`, `destination` and `error` of every record on
+    /// its own line, with `null` for the fields a record lacks.
+    #[default]
+    Json,
+    /// The status, source, destination and error of every record, each terminated by a NUL
+    /// character, with empty fields for the ones a record lacks.
+    Nul,
+}
+
+/// One record of the machine-readable output of `mmv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+struct Record<'a> {
+    status: &'a str,
+    source: Option<&'a str>,
+    destination: Option<&'a str>,
+    error: Option<&'a str>,
+}
+
+/// Record Reporter
+///
+/// The reporter of `mmv --json` and `mmv --print0`: writes a record for every moved file, or
+/// every planned one in a dry run, and for every file that is skipped, conflicts or fails, so
+/// that scripts do not have to parse `source -> destination` lines, which are ambiguous for
+/// names containing ` -> ` or line breaks. The status of a record is `completed`, `planned`,
+/// `skipped`, `conflict` or `failed`, or `error` for an error of the run that concerns no
+/// single file. Warnings are printed to stderr.
+///
+/// # Example
+///
+/// ```no
+/// execute(&plan, &mut RecordReporter::new(RecordFormat::Json, false))?;
+/// // {"status":"completed","source":"a.txt","destination":"b.txt","error":null}
+/// ```
+///
+#[derive(Debug)]
+pub struct RecordReporter<W: Write = Stdout> {
+    format: RecordFormat,
+    dry_run: bool,
+    failed: bool,
+    out: W,
+}
+
+impl RecordReporter {
+    /// A reporter writing to stdout.
+    pub fn new(format: RecordFormat, dry_run: bool) -> RecordReporter {
+        RecordReporter::with_writer(format, dry_run, io::stdout())
+    }
+}
+
+impl<W: Write> RecordReporter<W> {
+    pub fn with_writer(format: RecordFormat, dry_run: bool, out: W) -> RecordReporter<W> {
+        RecordReporter { format, dry_run, failed: false, out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write(&mut self, record: Record) {
+        // Like `println!`, but a closed output does not abort the moves
+        let _ = match self.format {
+            RecordFormat::Json => serde_json::to_writer(&mut self.out, &record)
+                .map_err(io::Error::from)
+                .and_then(|_| self.out.write_all(b"\n")),
+            RecordFormat::Nul => [record.status, record.source.unwrap_or_default(),
+                                 record.destination.unwrap_or_default(), record.error.unwrap_or_default()]
+                .iter()
+                .try_for_each(|field| self.out.write_all(field.as_bytes()).and_then(|_| self.out.write_all(b"\0"))),
+        };
+        let _ = self.out.flush();
+    }
+
+    fn write_entry(&mut self, status: &str, entry: &PlanEntry, error: Option<&str>) {
+        self.write(Record {
+            status,
+            source: Some(&entry.source),
+            destination: Some(&entry.destination),
+            error,
+        });
+    }
+}
+
+impl<W: Write> Reporter for RecordReporter<W> {
+    fn warning(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+
+    fn skipped(&mut self, skipped: &Skipped) {
+        self.write(Record {
+            status: "skipped",
+            source: Some(&skipped.source),
+            destination: None,
+            error: Some(&skipped.reason),
+        });
+    }
+
+    fn conflict(&mut self, entry: &PlanEntry, error: &str) {
+        self.failed = true;
+        self.write_entry("conflict", entry, Some(error));
+    }
+
+    fn planned(&mut self, entry: &PlanEntry) {
+        if self.dry_run {
+            self.write_entry("planned", entry, None);
+        }
+    }
+
+    fn completed(&mut self, entry: &PlanEntry) {
+        self.write_entry("completed", entry, None);
+    }
+
+    fn failed(&mut self, entry: &PlanEntry, error: &str) {
+        self.failed = true;
+        self.write_entry("failed", entry, Some(error));
+    }
+
+    /// Writes an `error` record, unless the error was already reported for the files it concerns.
+    fn error(&mut self, error: &str) {
+        if !self.failed {
+            self.write(Record { status: "error", source: None, destination: None, error: Some(error) });
+        }
+    }
+}
+
+
+#[test]
+fn test_record_reporter() {
+    use crate::move_plan::{self, Conflict, MovePlan};
+
+    let entry = |source: &str, destination: &str| PlanEntry {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        replace: false,
+        identity: None,
+    };
+    let mut plan = MovePlan {
+        entries: vec![entry("a -> b.txt", "new\nline.txt"), entry("c.txt", "new\nline.txt")],
+        skipped: vec![Skipped { source: "d.txt".to_string(), reason: "no tag".to_string() }],
+        conflicts: vec![Conflict::Duplicate {
+            sources: vec!["a -> b.txt".to_string(), "c.txt".to_string()],
+            destination: "new\nline.txt".to_string(),
+        }],
+        ..MovePlan::default()
+    };
+    let mut reporter = RecordReporter::with_writer(RecordFormat::Json, true, Vec::new());
+    let error = move_plan::preview(&plan, &mut reporter).unwrap_err();
+    reporter.error(&error);
+    let output = String::from_utf8(reporter.into_inner()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], r#"{"status":"skipped","source":"d.txt","destination":null,"error":"no tag"}"#);
+    assert_eq!(lines[1], r#"{"status":"conflict","source":"a -> b.txt","destination":"new\nline.txt","error":"mmv: a -> b.txt, c.txt would all be moved to new\nline.txt"}"#);
+
+    plan.retain(|entry| entry.source == "c.txt");
+    let mut reporter = RecordReporter::with_writer(RecordFormat::Nul, true, Vec::new());
+    move_plan::preview(&plan, &mut reporter).unwrap();
+    reporter.error("mmv: Files for pattern 'x*' not found");
+    assert_eq!(String::from_utf8(reporter.into_inner()).unwrap(),
+               "skipped\0d.txt\0\0no tag\0planned\0c.txt\0new\nline.txt\0\0\
+                error\0\0\0mmv: Files for pattern 'x*' not found\0");
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+
+/// Maximum length of a file name in bytes, `NAME_MAX` on Linux and most other systems.
+pub const NAME_MAX: usize = 255;
+
+/// Maximum length of a path in bytes, `PATH_MAX` on Linux.
+pub const PATH_MAX: usize = 4096;
+
+/// Longest extension kept when a name is truncated; longer ones are truncated with the name.
+const EXTENSION_MAX: usize = 16;
+
+/// A problem found in a computed destination path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A path component contained control characters, which were replaced with `_`.
+    ControlCharacters { component: String },
+    /// A path component was longer than `NAME_MAX` bytes and was truncated.
+    NameTooLong { name: String, length: usize },
+    /// The path is longer than `PATH_MAX` bytes. This cannot be fixed by truncating names.
+    PathTooLong { length: usize },
+    /// The file name is empty, e.g. from an empty capture or a name slugged to nothing.
+    EmptyName,
+    /// A directory of the path is empty, as in `out//a.txt`.
+    EmptyDirectory,
+}
+
+impl Violation {
+    /// Returns `true` if the path cannot be used even after sanitization.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Violation::PathTooLong { .. } | Violation::EmptyName | Violation::EmptyDirectory)
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ControlCharacters { component } =>
+                write!(f, "control characters in {:?} replaced with '_'", component),
+            Violation::NameTooLong { name, length } =>
+                write!(f, "name {:?} is {} bytes long, truncated to {}", name, length, NAME_MAX),
+            Violation::PathTooLong { length } =>
+                write!(f, "path is {} bytes long, the limit is {}", length, PATH_MAX),
+            Violation::EmptyName => write!(f, "the file name is empty"),
+            Violation::EmptyDirectory => write!(f, "a directory name of the path is empty"),
+        }
+    }
+}
+
+/// A destination path made safe to rename to, and the problems fixed or found on the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitized {
+    pub path: String,
+    pub violations: Vec<Violation>,
+}
+
+/// Truncates `text` to at most `max` bytes without splitting a character.
+fn truncate_bytes(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Truncate Name
+///
+/// Shortens a file name to `NAME_MAX` bytes, cutting the stem and keeping the extension so that
+/// `very…long.jpg` is still a `.jpg`.
+///
+fn truncate_name(name: &str) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 && name.len() - dot <= EXTENSION_MAX + 1 => {
+            let extension = &name[dot..];
+            format!("{}{}", truncate_bytes(&name[..dot], NAME_MAX - extension.len()), extension)
+        }
+        _ => truncate_bytes(name, NAME_MAX).to_string(),
+    }
+}
+
+/// Sanitize Path
+///
+/// Checks a computed destination path before any file is moved: control characters such as
+/// newlines captured from source names are replaced with `_`, and every component longer than
+/// `NAME_MAX` bytes is truncated, keeping its extension. A path longer than `PATH_MAX` bytes, an
+/// empty file name and an empty directory, which a rename would fail on or silently drop, are
+/// reported as fatal violations.
+///
+/// # Arguments
+///
+/// * `path` - The destination path rendered from the template.
+///
+/// # Returns
+///
+/// The sanitized path and every violation found, in the order of the components.
+///
+/// # Example
+///
+/// ```no
+/// let sanitized = sanitize_path("out/line\nbreak.txt");
+/// assert_eq!(sanitized.path, "out/line_break.txt");
+/// ```
+///
+pub fn sanitize_path(path: &str) -> Sanitized {
+    let mut violations = Vec::new();
+    let parts: Vec<&str> = path.split('/').collect();
+    // The first component is empty for absolute paths
+    if parts.iter().skip(1).take(parts.len().saturating_sub(2)).any(|part| part.is_empty()) {
+        violations.push(Violation::EmptyDirectory);
+    }
+    if parts[parts.len() - 1].is_empty() {
+        violations.push(Violation::EmptyName);
+    }
+    let components: Vec<String> = parts.into_iter()
+        .map(|component| {
+            let mut component = component.to_string();
+            if component.chars().any(char::is_control) {
+                violations.push(Violation::ControlCharacters { component: component.clone() });
+                component = component.chars()
+                    .map(|c| if c.is_control() { '_' } else { c })
+                    .collect();
+            }
+            if component.len() > NAME_MAX {
+                violations.push(Violation::NameTooLong {
+                    name: component.clone(),
+                    length: component.len(),
+                });
+                component = truncate_name(&component);
+            }
+            component
+        })
+        .collect();
+    let path = components.join("/");
+    if path.len() > PATH_MAX {
+        violations.push(Violation::PathTooLong { length: path.len() });
+    }
+    Sanitized { path, violations }
+}
+
+
+#[test]
+fn test_sanitize_control_characters() {
+    let sanitized = sanitize_path("out/line\nbreak\t.txt");
+    assert_eq!(sanitized.path, "out/line_break_.txt");
+    assert_eq!(sanitized.violations, vec![
+        Violation::ControlCharacters { component: "line\nbreak\t.txt".to_string() },
+    ]);
+    assert_eq!(sanitized.violations[0].to_string(),
+               "control characters in \"line\\nbreak\\t.txt\" replaced with '_'");
+    assert_eq!(sanitize_path("/abs/path.txt"),
+               Sanitized { path: "/abs/path.txt".to_string(), violations: vec![] });
+}
+
+#[test]
+fn test_sanitize_long_names() {
+    let long_name = format!("{}.jpg", "я".repeat(200));
+    let sanitized = sanitize_path(&format!("out/{}", long_name));
+    let name = sanitized.path.strip_prefix("out/").unwrap();
+    assert!(name.len() <= NAME_MAX);
+    assert_eq!(name, format!("{}.jpg", "я".repeat(125)));
+    assert_eq!(sanitized.violations, vec![Violation::NameTooLong { name: long_name, length: 404 }]);
+    assert!(!sanitized.violations[0].is_fatal());
+
+    let long_extension = format!("a.{}", "x".repeat(300));
+    assert_eq!(sanitize_path(&long_extension).path, truncate_bytes(&long_extension, NAME_MAX));
+
+    let deep = vec!["d".repeat(200); 25].join("/");
+    let sanitized = sanitize_path(&deep);
+    assert_eq!(sanitized.violations, vec![Violation::PathTooLong { length: 5024 }]);
+    assert!(sanitized.violations[0].is_fatal());
+}
+
+#[test]
+fn test_sanitize_empty_names() {
+    assert_eq!(sanitize_path("out/").violations, vec![Violation::EmptyName]);
+    assert_eq!(sanitize_path("").violations, vec![Violation::EmptyName]);
+    let sanitized = sanitize_path("sorted//a.txt");
+    assert_eq!(sanitized.violations, vec![Violation::EmptyDirectory]);
+    assert_eq!(sanitized.violations[0].to_string(), "a directory name of the path is empty");
+    assert!(sanitized.violations[0].is_fatal());
+    assert!(sanitize_path("/abs/a.txt").violations.is_empty());
+}
@@ -1,12 +1,67 @@
 use std::fs;
 use regex::Regex;
 
+/// Split Capture Names
+///
+/// Removes the names of named wildcards such as `*{year}` from a file pattern.
+///
+/// # Arguments
+///
+/// * `file_pattern` - A file pattern that may contain named wildcards.
+///
+/// # Returns
+///
+/// A tuple containing the file pattern with plain `*` wildcards and the name of every wildcard,
+/// `None` for unnamed ones.
+///
+/// # Example
+///
+/// ```no
+/// let (file_pattern, names) = split_capture_names("*{year}-*.csv");
+/// assert_eq!(file_pattern, "*-*.csv");
+/// assert_eq!(names, vec![Some("year".to_string()), None]);
+/// ```
+///
+pub fn split_capture_names(file_pattern: &str) -> (String, Vec<Option<String>>) {
+    let mut plain_pattern = String::new();
+    let mut names = Vec::new();
+    let mut rest = file_pattern;
+    while let Some(star) = rest.find('*') {
+        plain_pattern.push_str(&rest[..=star]);
+        rest = &rest[star + 1..];
+        let name = rest.strip_prefix('{')
+            .and_then(|braced| braced.split_once('}'))
+            .map(|(name, _)| name)
+            .filter(|name| is_capture_name(name));
+        match name {
+            Some(name) => {
+                names.push(Some(name.to_string()));
+                rest = &rest[name.len() + 2..];
+            }
+            None => names.push(None),
+        }
+    }
+    plain_pattern.push_str(rest);
+    (plain_pattern, names)
+}
+
+/// Is Capture Name
+///
+/// Checks that a name can be given to a wildcard: letters, digits and `_`, not starting with a
+/// digit so that `#{12}` always refers to a capture by number.
+///
+pub fn is_capture_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 /// Wildcard to Regex Pattern
 ///
 /// Converts a wildcard pattern to a regular expression pattern.
 ///
 /// The function takes a wildcard pattern as input and returns a regular expression pattern that
-/// matches the same set of strings as the wildcard.
+/// matches the same set of strings as the wildcard. Named wildcards such as `*{year}` match like
+/// plain `*` wildcards.
 ///
 /// # Arguments
 ///
@@ -26,6 +81,7 @@ use regex::Regex;
 /// This will convert the `wildcard` pattern to a regular expression pattern for matching files.
 ///
 pub fn wildcard_to_regex_pattern(wildcard: &str) -> String {
+    let (wildcard, _) = split_capture_names(wildcard);
     let regex_pattern = wildcard
         .chars()
         .map(|c| {
@@ -37,7 +93,8 @@ pub fn wildcard_to_regex_pattern(wildcard: &str) -> String {
         })
         .collect::<String>();
 
-    format!("^{}$", regex_pattern)
+    // `*` matches any characters, newlines included
+    format!("(?s)^{}$", regex_pattern)
 }
 
 /// Parse Full Path
@@ -72,17 +129,89 @@ pub fn parse_full_path(full_path: &str) -> (&str, &str) {
     }
 }
 
+/// Split Recursive Root
+///
+/// Splits a trailing `**` component off the directory part of a source pattern.
+///
+/// # Arguments
+///
+/// * `dir_path` - The directory part of a source pattern, e.g. `photos/**`.
+///
+/// # Returns
+///
+/// A tuple containing the directory the search starts from and whether its subdirectories are
+/// searched too.
+///
+/// # Example
+///
+/// ```no
+/// assert_eq!(split_recursive_root("photos/**"), ("photos", true));
+/// ```
+///
+pub fn split_recursive_root(dir_path: &str) -> (&str, bool) {
+    if dir_path == "**" {
+        ("", true)
+    } else if let Some(root) = dir_path.strip_suffix("/**") {
+        (root, true)
+    } else {
+        (dir_path, false)
+    }
+}
+
+/// Pattern Root
+///
+/// Returns the directory a source pattern is matched from, i.e. its directory part without a
+/// trailing `**` component.
+///
+pub fn pattern_root(full_path: &str) -> &str {
+    let (dir_path, _) = parse_full_path(full_path);
+    split_recursive_root(dir_path).0
+}
+
+/// Collects the entries of `dir_path` whose names match `regex`, descending into subdirectories
+/// if `recursive` is set. A recursive search selects the files inside directories rather than
+/// the directories themselves, while a flat one selects matching directories like files.
+fn collect_matching_files(dir_path: &str, regex: &Regex, recursive: bool,
+                          matching_files: &mut Vec<String>) -> Result<(), String> {
+    let read_path = if dir_path.is_empty() { "." } else { dir_path };
+    let entries = fs::read_dir(read_path)
+        .map_err(|_| "mmv: Not able to read directory".to_string())?;
+    for entry in entries.flatten() {
+        let filename = entry.file_name();
+        let filename = filename.to_string_lossy();
+        let full_path = if dir_path.is_empty() {
+            filename.to_string()
+        } else {
+            format!("{}/{}", dir_path, filename)
+        };
+        if recursive && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            collect_matching_files(&full_path, regex, recursive, matching_files)?;
+        } else if regex.is_match(&filename) {
+            matching_files.push(full_path);
+        }
+    }
+    Ok(())
+}
+
 /// Find Matching Files
 ///
 /// Finds files that match the given file pattern in the specified directory.
 ///
+/// If the last directory component of the pattern is `**`, as in `photos/**/*.jpg`, files are
+/// searched in all subdirectories too.
+///
+/// Without `**`, directories whose names match are selected like files and moved as a whole,
+/// as `mmv` always did. With `**` they are searched instead, so their files are moved one by
+/// one and the directories themselves are never selected: moving a directory together with the
+/// files in it would move those files twice.
+///
 /// # Arguments
 ///
 /// * `full_path` - A full path pattern that includes the directory and file pattern.
 ///
 /// # Returns
 ///
-/// A vector of strings representing the matching file paths or an error.
+/// A vector of strings representing the matching file paths, sorted by path, or an error.
 ///
 /// # Example
 ///
@@ -95,25 +224,16 @@ pub fn parse_full_path(full_path: &str) -> (&str, &str) {
 ///
 pub fn find_matching_files(full_path: &str) -> Result<Vec<String>, String> {
     let (dir_path, file_pattern) = parse_full_path(full_path);
+    let (root, recursive) = split_recursive_root(dir_path);
     let regex_pattern = wildcard_to_regex_pattern(file_pattern);
     let regex = Regex::new(&regex_pattern).unwrap();
     let mut matching_files: Vec<String> = vec![];
-    if let Ok(entries) = fs::read_dir(dir_path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                let filename = entry.file_name();
-                if regex.is_match(&*filename.to_string_lossy()) {
-                    let full_path = format!("{}/{}", dir_path, filename.to_string_lossy());
-                    matching_files.push(full_path);
-                }
-            }
-        }
-    } else {
-        return Err(format!("mmv: Not able to read directory"));
-    }
+    collect_matching_files(root, &regex, recursive, &mut matching_files)?;
     if matching_files.is_empty() {
         return Err(format!("mmv: Files for pattern '{}' not found", full_path));
     }
+    // Sorted so that the file counter `#{n}` follows the names rather than the directory order
+    matching_files.sort();
     Ok(matching_files)
 }
 
@@ -121,9 +241,20 @@ pub fn find_matching_files(full_path: &str) -> Result<Vec<String>, String> {
 #[test]
 fn test_wildcard_to_regex_pattern() {
     assert_eq!(wildcard_to_regex_pattern("some_*file*_name.txt"),
-               r"^some_.*file.*_name\.txt$");
+               r"(?s)^some_.*file.*_name\.txt$");
     assert_eq!(wildcard_to_regex_pattern("*file*_name.*"),
-               r"^.*file.*_name\..*$");
+               r"(?s)^.*file.*_name\..*$");
+    assert_eq!(wildcard_to_regex_pattern("*{year}-*{month}.csv"),
+               r"(?s)^.*\-.*\.csv$");
+}
+
+#[test]
+fn test_split_capture_names() {
+    assert_eq!(split_capture_names("*{year}-*{month}.csv"),
+               ("*-*.csv".to_string(), vec![Some("year".to_string()), Some("month".to_string())]));
+    assert_eq!(split_capture_names("*_*{1}{x"),
+               ("*_*{1}{x".to_string(), vec![None, None]));
+    assert_eq!(split_capture_names("file.txt"), ("file.txt".to_string(), vec![]));
 }
 
 
@@ -165,4 +296,42 @@ fn test_find_matching_files() {
     assert_eq!(res_files.sort(), result.unwrap().sort());
 }
 
+#[test]
+fn test_find_matching_directories() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = |name: &str| temp_dir.path().join(name).to_string_lossy().to_string();
+    fs::create_dir_all(path("photos/trip.jpg")).expect("Failed to create directories");
+    fs::write(path("photos/a.jpg"), "").expect("Failed to write file");
+    fs::write(path("photos/trip.jpg/b.jpg"), "").expect("Failed to write file");
+
+    assert_eq!(find_matching_files(&path("photos/*.jpg")).unwrap(),
+               vec![path("photos/a.jpg"), path("photos/trip.jpg")]);
+    assert_eq!(find_matching_files(&path("photos/**/*.jpg")).unwrap(),
+               vec![path("photos/a.jpg"), path("photos/trip.jpg/b.jpg")]);
+}
 
+#[test]
+fn test_split_recursive_root() {
+    assert_eq!(split_recursive_root("photos/**"), ("photos", true));
+    assert_eq!(split_recursive_root("**"), ("", true));
+    assert_eq!(split_recursive_root("photos"), ("photos", false));
+    assert_eq!(pattern_root("photos/**/*.jpg"), "photos");
+}
+
+#[test]
+fn test_find_matching_files_recursive() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let root = temp_dir.path().join("photos");
+    for file_path in ["a.jpg", "2023/b.jpg", "2023/trip/c.jpg", "2023/trip/d.png"] {
+        let full_path = root.join(file_path);
+        fs::create_dir_all(full_path.parent().unwrap()).expect("Failed to create parent directories");
+        fs::File::create(&full_path).expect("Failed to create file");
+    }
+
+    let pattern = root.join("**/*.jpg");
+    let result = find_matching_files(&pattern.to_string_lossy()).unwrap();
+    let root = root.to_string_lossy();
+    assert_eq!(result, vec![format!("{}/2023/b.jpg", root),
+                            format!("{}/2023/trip/c.jpg", root),
+                            format!("{}/a.jpg", root)]);
+}
@@ -0,0 +1,986 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use crate::build_target_path::GenericParts;
+use crate::search_by_pattern::is_capture_name;
+use crate::tokens::{NumberFormat, Resolved, Tokens};
+
+/// Destination Template
+///
+/// A destination pattern parsed into literal text, markers and tokens. The pattern is parsed and validated
+/// once, before any file is touched, and then rendered for every matched file.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pattern: String,
+    segments: Vec<Segment>,
+    /// Filters applied to the whole name of every destination, e.g. `|slug`.
+    name_filters: Vec<Filter>,
+}
+
+/// A piece of a destination template: literal text, a marker or a token.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Literal(String),
+    Marker(Marker),
+    Token(Token),
+}
+
+/// Name of the file counter in braced markers, `#{n}`.
+pub const COUNTER: &str = "n";
+
+/// A `#N` or `#{...}` marker referring to a capture of the source pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub capture: Capture,
+    /// Byte offset of the marker in the full destination pattern.
+    pub position: usize,
+    pub modifier: Modifier,
+    /// Filters the value goes through, in order, as in `#{1|date:%d-%m-%Y}`.
+    pub filters: Vec<Filter>,
+}
+
+/// The capture a marker refers to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Capture {
+    /// `#N` or `#{N}` - the N-th wildcard, 1-based.
+    Index(usize),
+    /// `#{name}` - the wildcard written as `*{name}`.
+    Name(String),
+    /// `#{n}` - the 1-based position of the file among the matched files.
+    Counter,
+}
+
+impl fmt::Display for Capture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capture::Index(index) => write!(f, "#{}", index),
+            Capture::Name(name) => write!(f, "#{{{}}}", name),
+            Capture::Counter => write!(f, "#{{{}}}", COUNTER),
+        }
+    }
+}
+
+/// How a marker treats its capture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Modifier {
+    /// `#N`, `#{N}` or `#{name}` - the capture itself.
+    None,
+    /// `#{N:-default}` or `#{name:-default}` - the capture, or `default` if the capture is empty.
+    Default(Vec<Segment>),
+    /// `#{N:+segment}` or `#{name:+segment}` - `segment` if the capture is non-empty, and nothing
+    /// otherwise.
+    Conditional(Vec<Segment>),
+    /// `#{N+1}`, `#{n*10:03}` or `#{N:02}` - the capture as an integer, with arithmetic applied
+    /// and formatted with an optional width.
+    Number(Arithmetic),
+}
+
+/// Integer arithmetic applied to a numeric capture or counter, e.g. the `*10+1:03` of `#{1*10+1:03}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arithmetic {
+    /// Operations applied to the capture, with the usual precedence of `*`, `/` and `%` over
+    /// `+` and `-`.
+    pub operations: Vec<(Operator, i64)>,
+    pub format: Option<NumberFormat>,
+}
+
+/// An operator of a marker expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl Operator {
+    fn from_char(c: char) -> Option<Operator> {
+        match c {
+            '+' => Some(Operator::Add),
+            '-' => Some(Operator::Subtract),
+            '*' => Some(Operator::Multiply),
+            '/' => Some(Operator::Divide),
+            '%' => Some(Operator::Remainder),
+            _ => None,
+        }
+    }
+
+    fn apply(self, left: i64, right: i64) -> Option<i64> {
+        match self {
+            Operator::Add => left.checked_add(right),
+            Operator::Subtract => left.checked_sub(right),
+            Operator::Multiply => left.checked_mul(right),
+            Operator::Divide => left.checked_div(right),
+            Operator::Remainder => left.checked_rem(right),
+        }
+    }
+}
+
+impl Arithmetic {
+    /// Evaluate
+    ///
+    /// Applies the operations to `value`, returning `None` on overflow.
+    ///
+    pub fn evaluate(&self, value: i64) -> Option<i64> {
+        // The sum of the terms evaluated so far, and the term being multiplied out
+        let mut sum = 0i64;
+        let mut term = value;
+        for &(operator, operand) in &self.operations {
+            match operator {
+                Operator::Add => {
+                    sum = sum.checked_add(term)?;
+                    term = operand;
+                }
+                Operator::Subtract => {
+                    sum = sum.checked_add(term)?;
+                    term = operand.checked_neg()?;
+                }
+                _ => term = operator.apply(term, operand)?,
+            }
+        }
+        sum.checked_add(term)
+    }
+
+    /// Formats the result of `evaluate` with the width of the marker.
+    pub fn format(&self, value: i64) -> String {
+        match self.format {
+            Some(format) => format.format(value),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// A `{name}` or `{name:argument}` token resolved by a `TokenProvider` for every source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub name: String,
+    pub argument: Option<String>,
+    /// Byte offset of the token in the full destination pattern.
+    pub position: usize,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.argument {
+            Some(argument) => write!(f, "{{{}:{}}}", self.name, argument),
+            None => write!(f, "{{{}}}", self.name),
+        }
+    }
+}
+
+/// A `|name` or `|name:argument` filter of a marker, applied by a `FilterProvider`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub name: String,
+    pub argument: Option<String>,
+    /// Byte offset of the filter in the full destination pattern.
+    pub position: usize,
+}
+
+#[cfg(test)]
+impl Token {
+    /// A token at the start of a pattern, for the tests of token providers.
+    pub(crate) fn new(name: &str, argument: Option<&str>) -> Token {
+        Token { name: name.to_string(), argument: argument.map(|a| a.to_string()), position: 0 }
+    }
+}
+
+#[cfg(test)]
+impl Filter {
+    /// A filter at the start of a pattern, for the tests of filter providers.
+    pub(crate) fn new(name: &str, argument: Option<&str>) -> Filter {
+        Filter { name: name.to_string(), argument: argument.map(|a| a.to_string()), position: 0 }
+    }
+}
+
+impl Filter {
+    /// Parses a filter given outside of a pattern, such as `slug` or `translit:icao`.
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let (name, argument) = match spec.split_once(':') {
+            Some((name, argument)) => (name, Some(argument.to_string())),
+            None => (spec, None),
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("mmv: Invalid filter '{}', expected a name such as 'slug'", spec));
+        }
+        Ok(Filter { name: name.to_string(), argument, position: 0 })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.argument {
+            Some(argument) => write!(f, "|{}:{}", self.name, argument),
+            None => write!(f, "|{}", self.name),
+        }
+    }
+}
+
+/// The source file a destination is rendered for.
+pub struct SourceFile<'a> {
+    /// Path of the matched file.
+    pub path: &'a Path,
+    /// The parts of the filename matched by the `*` wildcards.
+    pub captures: &'a GenericParts,
+    /// The directory the source pattern was matched from, an ancestor of `path`.
+    pub root: &'a Path,
+    /// The 1-based position of the file among the matched files.
+    pub counter: usize,
+}
+
+/// Captures of a file matched without wildcards.
+static NO_CAPTURES: GenericParts = GenericParts { positional: Vec::new(), named: BTreeMap::new() };
+
+impl<'a> SourceFile<'a> {
+    /// Creates a source file without captures, rooted at its own directory.
+    pub fn new(path: &'a Path) -> SourceFile<'a> {
+        SourceFile {
+            path,
+            captures: &NO_CAPTURES,
+            root: path.parent().unwrap_or(Path::new("")),
+            counter: 1,
+        }
+    }
+}
+
+/// Error At
+///
+/// Formats a user error for the destination pattern with a caret pointing at `position`.
+///
+fn error_at(pattern: &str, position: usize, message: &str) -> String {
+    let column = pattern[..position.min(pattern.len())].chars().count();
+    format!("mmv: Invalid destination pattern: {}\n    {}\n    {}^",
+            message, pattern, " ".repeat(column))
+}
+
+/// Recursive descent parser over a destination pattern.
+struct Parser<'a> {
+    pattern: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.pattern[self.position..].chars().next()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.pattern[self.position..]
+    }
+
+    fn error(&self, position: usize, message: &str) -> String {
+        error_at(self.pattern, position, message)
+    }
+
+    /// Parses segments until the end of the pattern or, if `nested`, until a closing `}`.
+    fn parse_segments(&mut self, nested: bool) -> Result<Vec<Segment>, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        while let Some(c) = self.peek() {
+            if nested && c == '}' {
+                break;
+            }
+            let next = self.rest()[c.len_utf8()..].chars().next();
+            if c == '#' && next.is_some_and(|n| n.is_ascii_digit() || n == '{') {
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Marker(self.parse_marker()?));
+            } else if (c == '{' || c == '}') && next == Some(c) {
+                // `{{` and `}}` stand for literal braces
+                literal.push(c);
+                self.position += 2;
+            } else if c == '{' {
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(Segment::Token(self.parse_token()?));
+            } else {
+                literal.push(c);
+                self.position += c.len_utf8();
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(segments)
+    }
+
+    fn parse_index(&mut self) -> Result<usize, String> {
+        let start = self.position;
+        let digits = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+        if digits == 0 {
+            return Err(self.error(start, "expected a capture number"));
+        }
+        self.position += digits;
+        self.pattern[start..self.position].parse()
+            .map_err(|_| self.error(start, "capture number is too large"))
+    }
+
+    /// Parses the capture number or name of a braced marker.
+    fn parse_capture(&mut self) -> Result<Capture, String> {
+        if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            return Ok(Capture::Index(self.parse_index()?));
+        }
+        let name_len = self.rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        let name = &self.rest()[..name_len];
+        if !is_capture_name(name) {
+            return Err(self.error(self.position, "expected a capture number or name"));
+        }
+        self.position += name_len;
+        if name == COUNTER {
+            return Ok(Capture::Counter);
+        }
+        Ok(Capture::Name(name.to_string()))
+    }
+
+    /// Parses the operations and the width following the capture of a braced marker, if any.
+    fn parse_arithmetic(&mut self) -> Result<Option<Arithmetic>, String> {
+        let mut operations = Vec::new();
+        while let Some(operator) = self.peek().and_then(Operator::from_char) {
+            let operator_position = self.position;
+            self.position += 1;
+            let start = self.position;
+            let digits = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+            if digits == 0 {
+                return Err(self.error(start, "expected a number"));
+            }
+            self.position += digits;
+            let operand: i64 = self.pattern[start..self.position].parse()
+                .map_err(|_| self.error(start, "number is too large"))?;
+            if operand == 0 && matches!(operator, Operator::Divide | Operator::Remainder) {
+                return Err(self.error(operator_position, "division by zero"));
+            }
+            operations.push((operator, operand));
+        }
+        let format = if self.peek() == Some(':')
+            && self.rest()[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            self.position += 1;
+            let start = self.position;
+            let digits = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+            self.position += digits;
+            Some(NumberFormat::parse(&self.pattern[start..self.position])
+                .map_err(|message| self.error(start, &message))?)
+        } else {
+            None
+        };
+        if operations.is_empty() && format.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Arithmetic { operations, format }))
+    }
+
+    fn parse_token(&mut self) -> Result<Token, String> {
+        let position = self.position;
+        self.position += 1;
+        let name_len = self.rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        if name_len == 0 {
+            return Err(self.error(self.position,
+                                  "expected a token name, use `{{` for a literal `{`"));
+        }
+        let name = self.rest()[..name_len].to_string();
+        self.position += name_len;
+        let argument = if self.peek() == Some(':') {
+            self.position += 1;
+            let end = self.rest().find('}')
+                .ok_or_else(|| self.error(position, "unclosed token, expected `}`"))?;
+            let argument = self.rest()[..end].to_string();
+            self.position += end;
+            Some(argument)
+        } else {
+            None
+        };
+        match self.peek() {
+            Some('}') => {
+                self.position += 1;
+                Ok(Token { name, argument, position })
+            }
+            Some(c) => Err(self.error(self.position,
+                                      &format!("unexpected `{}`, expected `}}` or `:`", c))),
+            None => Err(self.error(position, "unclosed token, expected `}`")),
+        }
+    }
+
+    /// Parses a `|name` or `|name:argument` filter, whose argument runs up to the next `|` or `}`.
+    fn parse_filter(&mut self) -> Result<Filter, String> {
+        let position = self.position;
+        self.position += 1;
+        let name_len = self.rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        if name_len == 0 {
+            return Err(self.error(self.position, "expected a filter name"));
+        }
+        let name = self.rest()[..name_len].to_string();
+        self.position += name_len;
+        let argument = if self.peek() == Some(':') {
+            self.position += 1;
+            let end = self.rest().find(['|', '}']).unwrap_or(self.rest().len());
+            let argument = self.rest()[..end].to_string();
+            self.position += end;
+            Some(argument)
+        } else {
+            None
+        };
+        Ok(Filter { name, argument, position })
+    }
+
+    fn parse_marker(&mut self) -> Result<Marker, String> {
+        let position = self.position;
+        self.position += 1;
+        if self.peek() != Some('{') {
+            let capture = Capture::Index(self.parse_index()?);
+            return Ok(Marker { capture, position, modifier: Modifier::None, filters: Vec::new() });
+        }
+        self.position += 1;
+        let capture = self.parse_capture()?;
+        let arithmetic = self.parse_arithmetic()?;
+        let mut filters = Vec::new();
+        while self.peek() == Some('|') {
+            filters.push(self.parse_filter()?);
+        }
+        let modifier = if let Some(arithmetic) = arithmetic {
+            Modifier::Number(arithmetic)
+        } else if !filters.is_empty() {
+            Modifier::None
+        } else if self.rest().starts_with(":-") {
+            self.position += 2;
+            Modifier::Default(self.parse_segments(true)?)
+        } else if self.rest().starts_with(":+") {
+            self.position += 2;
+            Modifier::Conditional(self.parse_segments(true)?)
+        } else {
+            Modifier::None
+        };
+        match self.peek() {
+            Some('}') => {
+                self.position += 1;
+                Ok(Marker { capture, position, modifier, filters })
+            }
+            Some(c) => Err(self.error(self.position,
+                                      &format!("unexpected `{}`, expected `}}`, `:-`, `:+`, `|` or an operator", c))),
+            None => Err(self.error(position, "unclosed marker, expected `}`")),
+        }
+    }
+}
+
+impl Template {
+    /// Parse
+    ///
+    /// Parses a full destination pattern. Markers are recognized in every component of the path,
+    /// so `sorted/#2/#1.#2` sorts files into one directory per extension.
+    ///
+    /// # Arguments
+    ///
+    /// * `full_output_path_pattern` - The full output path pattern with placeholders.
+    ///
+    /// # Returns
+    ///
+    /// The parsed template, or a user error with a caret pointing at the malformed marker.
+    ///
+    /// # Example
+    ///
+    /// ```no
+    /// let template = Template::parse("path2/to/changed_#1_filename.#2")?;
+    /// ```
+    ///
+    pub fn parse(full_output_path_pattern: &str) -> Result<Template, String> {
+        let mut parser = Parser { pattern: full_output_path_pattern, position: 0 };
+        let segments = parser.parse_segments(false)?;
+        Ok(Template { pattern: full_output_path_pattern.to_string(), segments, name_filters: Vec::new() })
+    }
+
+    /// Validate
+    ///
+    /// Checks that every marker refers to one of the captures of the source pattern, by number or
+    /// by name, and that every token is known and has a valid argument.
+    ///
+    /// # Arguments
+    ///
+    /// * `capture_names` - The name of every `*` wildcard in the source pattern, `None` for
+    ///   unnamed ones.
+    /// * `tokens` - The token providers available to the template.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the template is valid, or a user error with a caret pointing at the first
+    /// invalid marker or token.
+    ///
+    pub fn validate(&self, capture_names: &[Option<String>], tokens: &Tokens) -> Result<(), String> {
+        self.validate_segments(&self.segments, capture_names, tokens)?;
+        for filter in &self.name_filters {
+            let provider = tokens.find_filter(&filter.name)
+                .ok_or_else(|| format!("mmv: Invalid name filter '{}': unknown filter", filter))?;
+            provider.validate(filter)
+                .map_err(|message| format!("mmv: Invalid name filter '{}': {}", filter, message))?;
+        }
+        Ok(())
+    }
+
+    /// The directory at the start of the pattern that contains no marker or token, ending with
+    /// `/`, such as `/mnt/data/` of `/mnt/data/#1/{mtime:%Y}.txt`; every destination is rendered
+    /// in it.
+    pub fn fixed_directory(&self) -> &str {
+        match self.segments.first() {
+            Some(Segment::Literal(text)) => &text[..text.rfind('/').map_or(0, |slash| slash + 1)],
+            _ => "",
+        }
+    }
+
+    /// With Name Filters
+    ///
+    /// Sets filters applied to the whole file name of every rendered destination, such as `slug`
+    /// to make names safe for web uploads. The stem and the extension of the name are filtered
+    /// separately, so `Отчёт.PDF` becomes `otchyot.pdf`; directories are left as they are.
+    ///
+    pub fn with_name_filters(mut self, name_filters: Vec<Filter>) -> Template {
+        self.name_filters = name_filters;
+        self
+    }
+
+    fn validate_token(&self, token: &Token, tokens: &Tokens) -> Result<(), String> {
+        match tokens.find(&token.name) {
+            Some(provider) => provider.validate(token)
+                .map_err(|message| error_at(&self.pattern, token.position, &message)),
+            None => Err(error_at(&self.pattern, token.position,
+                                 &format!("unknown token '{}', use `{{{{` for a literal `{{`",
+                                          token.name))),
+        }
+    }
+
+    fn validate_filter(&self, filter: &Filter, tokens: &Tokens) -> Result<(), String> {
+        match tokens.find_filter(&filter.name) {
+            Some(provider) => provider.validate(filter)
+                .map_err(|message| error_at(&self.pattern, filter.position, &message)),
+            None => Err(error_at(&self.pattern, filter.position,
+                                 &format!("unknown filter '{}'", filter.name))),
+        }
+    }
+
+    fn validate_segments(&self, segments: &[Segment], capture_names: &[Option<String>],
+                         tokens: &Tokens) -> Result<(), String> {
+        for segment in segments {
+            if let Segment::Token(token) = segment {
+                self.validate_token(token, tokens)?;
+            }
+            if let Segment::Marker(marker) = segment {
+                match &marker.capture {
+                    Capture::Index(0) =>
+                        return Err(error_at(&self.pattern, marker.position,
+                                            "there is no capture #0, captures are numbered from #1")),
+                    Capture::Index(index) if *index > capture_names.len() =>
+                        return Err(error_at(&self.pattern, marker.position,
+                                            &format!("there is no capture #{}, the source pattern has {} wildcard(s)",
+                                                     index, capture_names.len()))),
+                    Capture::Name(name) if !capture_names.contains(&Some(name.clone())) =>
+                        return Err(error_at(&self.pattern, marker.position,
+                                            &format!("there is no capture named '{}', name a wildcard as *{{{}}}",
+                                                     name, name))),
+                    _ => {}
+                }
+                for filter in &marker.filters {
+                    self.validate_filter(filter, tokens)?;
+                }
+                match &marker.modifier {
+                    Modifier::None | Modifier::Number(_) => {}
+                    Modifier::Default(nested) | Modifier::Conditional(nested) =>
+                        self.validate_segments(nested, capture_names, tokens)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Render
+    ///
+    /// Builds the target path for one file by substituting its captures and token values into
+    /// the template. The template must have been validated: a marker without a capture is an
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The source file and the parts of its filename matched by the `*` wildcards.
+    /// * `tokens` - The token providers the template was validated with.
+    ///
+    /// # Returns
+    ///
+    /// The target path, `Resolved::Skip` if a token has no value for this file and the file should
+    /// be left where it is, or an error if a token cannot be resolved.
+    ///
+    pub fn render(&self, file: &SourceFile, tokens: &Tokens) -> Result<Resolved, String> {
+        let mut path = String::new();
+        if let Some(reason) = render_segments(&self.segments, file, tokens, &mut path)? {
+            return Ok(Resolved::Skip(reason));
+        }
+        if self.name_filters.is_empty() {
+            return Ok(Resolved::Value(path));
+        }
+        let (directory, name) = match path.rfind('/') {
+            Some(slash) => path.split_at(slash + 1),
+            None => ("", path.as_str()),
+        };
+        let (mut stem, mut extension) = match name.rfind('.') {
+            Some(dot) if dot > 0 => (name[..dot].to_string(), name[dot + 1..].to_string()),
+            _ => (name.to_string(), String::new()),
+        };
+        for filter in &self.name_filters {
+            let provider = tokens.find_filter(&filter.name)
+                .ok_or_else(|| format!("mmv: Unknown filter {}", filter))?;
+            for part in [&mut stem, &mut extension] {
+                if part.is_empty() {
+                    continue;
+                }
+                let filtered = provider.apply(filter, part)
+                    .map_err(|e| format!("mmv: Not able to apply {} to the name of {}: {}",
+                                         filter, file.path.to_string_lossy(), e))?;
+                match filtered {
+                    Resolved::Value(value) => *part = value,
+                    Resolved::Skip(reason) => return Ok(Resolved::Skip(reason)),
+                }
+            }
+        }
+        if extension.is_empty() {
+            return Ok(Resolved::Value(format!("{}{}", directory, stem)));
+        }
+        Ok(Resolved::Value(format!("{}{}.{}", directory, stem, extension)))
+    }
+}
+
+/// Computes the value of a marker for a file: its capture, with arithmetic and filters applied.
+fn marker_value(marker: &Marker, file: &SourceFile, tokens: &Tokens) -> Result<Resolved, String> {
+    let counter = file.counter.to_string();
+    let capture = match &marker.capture {
+        Capture::Index(index) => index.checked_sub(1).and_then(|index| file.captures.positional.get(index)),
+        Capture::Name(name) => file.captures.named.get(name),
+        Capture::Counter => Some(&counter),
+    };
+    // Only reachable for a template that was not validated against the source pattern
+    let mut value = capture
+        .ok_or_else(|| format!("mmv: There is no capture {} for {}", marker.capture, file.path.to_string_lossy()))?
+        .to_string();
+    if let Modifier::Number(arithmetic) = &marker.modifier {
+        let number = value.parse::<i64>()
+            .map_err(|_| format!("mmv: Not able to compute {} for {}: '{}' is not a number",
+                                 marker.capture, file.path.to_string_lossy(), value))?;
+        let result = arithmetic.evaluate(number)
+            .ok_or_else(|| format!("mmv: Not able to compute {} for {}: overflow",
+                                   marker.capture, file.path.to_string_lossy()))?;
+        value = arithmetic.format(result);
+    }
+    for filter in &marker.filters {
+        let provider = tokens.find_filter(&filter.name)
+            .ok_or_else(|| format!("mmv: Unknown filter {}", filter))?;
+        let filtered = provider.apply(filter, &value)
+            .map_err(|e| format!("mmv: Not able to apply {} to {} for {}: {}",
+                                 filter, marker.capture, file.path.to_string_lossy(), e))?;
+        match filtered {
+            Resolved::Value(filtered) => value = filtered,
+            Resolved::Skip(reason) => return Ok(Resolved::Skip(reason)),
+        }
+    }
+    Ok(Resolved::Value(value))
+}
+
+/// Renders segments into `output`, returning the reason to skip the file if a token has no value.
+fn render_segments(segments: &[Segment], file: &SourceFile, tokens: &Tokens,
+                   output: &mut String) -> Result<Option<String>, String> {
+    for segment in segments {
+        match segment {
+            Segment::Literal(text) => output.push_str(text),
+            Segment::Token(token) => {
+                let provider = tokens.find(&token.name)
+                    .ok_or_else(|| format!("mmv: Unknown token {}", token))?;
+                let value = provider.resolve(token, file)
+                    .map_err(|e| format!("mmv: Not able to resolve {} for {}: {}",
+                                         token, file.path.to_string_lossy(), e))?;
+                match value {
+                    Resolved::Value(value) => output.push_str(&value),
+                    Resolved::Skip(reason) => return Ok(Some(reason)),
+                }
+            }
+            Segment::Marker(marker) => {
+                let value = match marker_value(marker, file, tokens)? {
+                    Resolved::Value(value) => value,
+                    Resolved::Skip(reason) => return Ok(Some(reason)),
+                };
+                let nested = match &marker.modifier {
+                    Modifier::Default(default) if value.is_empty() => Some(default),
+                    Modifier::Conditional(segment) if !value.is_empty() => Some(segment),
+                    Modifier::None | Modifier::Default(_) | Modifier::Number(_) => {
+                        output.push_str(&value);
+                        None
+                    }
+                    Modifier::Conditional(_) => None,
+                };
+                if let Some(nested) = nested {
+                    if let Some(reason) = render_segments(nested, file, tokens, output)? {
+                        return Ok(Some(reason));
+                    }
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+
+#[test]
+fn test_parse_template() {
+    let template = Template::parse("path/to/#1_#{2:-none}.txt").unwrap();
+    assert_eq!(template.segments, vec![
+        Segment::Literal("path/to/".to_string()),
+        Segment::Marker(Marker {
+            capture: Capture::Index(1),
+            position: 8,
+            modifier: Modifier::None,
+            filters: vec![],
+        }),
+        Segment::Literal("_".to_string()),
+        Segment::Marker(Marker {
+            capture: Capture::Index(2),
+            position: 11,
+            modifier: Modifier::Default(vec![Segment::Literal("none".to_string())]),
+            filters: vec![],
+        }),
+        Segment::Literal(".txt".to_string()),
+    ]);
+    assert_eq!(Template::parse("a#b#").unwrap().segments,
+               vec![Segment::Literal("a#b#".to_string())]);
+    assert_eq!(Template::parse("#{12}#{year}").unwrap().segments, vec![
+        Segment::Marker(Marker {
+            capture: Capture::Index(12),
+            position: 0,
+            modifier: Modifier::None,
+            filters: vec![],
+        }),
+        Segment::Marker(Marker {
+            capture: Capture::Name("year".to_string()),
+            position: 5,
+            modifier: Modifier::None,
+            filters: vec![],
+        }),
+    ]);
+}
+
+#[test]
+fn test_parse_template_errors() {
+    assert_eq!(Template::parse("out/#{-x}.txt").unwrap_err(),
+               "mmv: Invalid destination pattern: expected a capture number or name\n    out/#{-x}.txt\n          ^");
+    assert_eq!(Template::parse("out/#{1.txt").unwrap_err(),
+               "mmv: Invalid destination pattern: unexpected `.`, expected `}`, `:-`, `:+`, `|` or an operator\n    out/#{1.txt\n           ^");
+    assert_eq!(Template::parse("out/#{1:-x").unwrap_err(),
+               "mmv: Invalid destination pattern: unclosed marker, expected `}`\n    out/#{1:-x\n        ^");
+    assert!(Template::parse("#99999999999999999999999").is_err());
+    assert_eq!(Template::parse("out/{mtime:%Y").unwrap_err(),
+               "mmv: Invalid destination pattern: unclosed token, expected `}`\n    out/{mtime:%Y\n        ^");
+    assert_eq!(Template::parse("out/{ x}").unwrap_err(),
+               "mmv: Invalid destination pattern: expected a token name, use `{{` for a literal `{`\n    out/{ x}\n         ^");
+}
+
+#[test]
+fn test_parse_template_tokens() {
+    let template = Template::parse("{{a}}/{mtime:%H:%M}/{size}").unwrap();
+    assert_eq!(template.segments, vec![
+        Segment::Literal("{a}/".to_string()),
+        Segment::Token(Token {
+            name: "mtime".to_string(),
+            argument: Some("%H:%M".to_string()),
+            position: 6,
+        }),
+        Segment::Literal("/".to_string()),
+        Segment::Token(Token { name: "size".to_string(), argument: None, position: 20 }),
+    ]);
+    assert_eq!(template.fixed_directory(), "{a}/");
+    assert_eq!(Template::parse("/mnt/x:y/out/#1/a.txt").unwrap().fixed_directory(), "/mnt/x:y/out/");
+    assert_eq!(Template::parse("#1/a.txt").unwrap().fixed_directory(), "");
+    assert_eq!(Template::parse("all.txt").unwrap().fixed_directory(), "");
+}
+
+#[test]
+fn test_validate_template() {
+    let tokens = Tokens::default();
+    let two = [None, None];
+    assert!(Template::parse("out/#1#{2:+_#2}").unwrap().validate(&two, &tokens).is_ok());
+    assert_eq!(Template::parse("out/#0.txt").unwrap().validate(&two, &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: there is no capture #0, captures are numbered from #1\n    out/#0.txt\n        ^");
+    assert_eq!(Template::parse("out/#1#{2:+_#3}").unwrap().validate(&two, &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: there is no capture #3, the source pattern has 2 wildcard(s)\n    out/#1#{2:+_#3}\n                ^");
+    let named = [Some("year".to_string())];
+    assert!(Template::parse("out/#{year}").unwrap().validate(&named, &tokens).is_ok());
+    assert_eq!(Template::parse("out/#{month}").unwrap().validate(&named, &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: there is no capture named 'month', name a wildcard as *{month}\n    out/#{month}\n        ^");
+    assert_eq!(Template::parse("out/{mtim}").unwrap().validate(&[], &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: unknown token 'mtim', use `{{` for a literal `{`\n    out/{mtim}\n        ^");
+    assert_eq!(Template::parse("out/{size:kb}").unwrap().validate(&[], &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: unknown size format 'kb', expected 'bytes' or 'human'\n    out/{size:kb}\n        ^");
+}
+
+#[test]
+fn test_render_template_directories() {
+    let tokens = Tokens::default();
+    let captures = GenericParts {
+        positional: vec!["photo".to_string(), "jpg".to_string()],
+        named: BTreeMap::new(),
+    };
+    let file = SourceFile {
+        path: Path::new("photo.jpg"),
+        captures: &captures,
+        root: Path::new(""),
+        counter: 1,
+    };
+    let template = Template::parse("sorted/#2/#1.#2").unwrap();
+    template.validate(&[None, None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap(),
+               Resolved::Value("sorted/jpg/photo.jpg".to_string()));
+    let template = Template::parse("#1.#2").unwrap();
+    template.validate(&[None, None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap(), Resolved::Value("photo.jpg".to_string()));
+    assert_eq!(Template::parse("#0").unwrap().render(&file, &tokens).unwrap_err(),
+               "mmv: There is no capture #0 for photo.jpg");
+    assert_eq!(Template::parse("#3").unwrap().render(&file, &tokens).unwrap_err(),
+               "mmv: There is no capture #3 for photo.jpg");
+}
+
+#[test]
+fn test_render_template_tokens() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path = temp_dir.path().join("photo.jpg");
+    std::fs::write(&path, "hello").expect("Failed to write file");
+    let tokens = Tokens::default();
+    let captures = GenericParts { positional: vec!["photo".to_string()], named: BTreeMap::new() };
+    let file = SourceFile { path: &path, captures: &captures, root: temp_dir.path(), counter: 1 };
+    let template = Template::parse("by_size/{size}/#1.jpg").unwrap();
+    template.validate(&[None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap(),
+               Resolved::Value("by_size/5/photo.jpg".to_string()));
+
+    let missing = temp_dir.path().join("missing.jpg");
+    let file = SourceFile { path: &missing, captures: &captures, root: temp_dir.path(), counter: 1 };
+    assert!(template.render(&file, &tokens).unwrap_err()
+        .starts_with("mmv: Not able to resolve {size} for "));
+}
+
+#[test]
+fn test_parse_template_arithmetic() {
+    assert_eq!(Template::parse("#{1*10+1:03}").unwrap().segments, vec![
+        Segment::Marker(Marker {
+            capture: Capture::Index(1),
+            position: 0,
+            modifier: Modifier::Number(Arithmetic {
+                operations: vec![(Operator::Multiply, 10), (Operator::Add, 1)],
+                format: Some(NumberFormat { width: 3, zero_pad: true }),
+            }),
+            filters: vec![],
+        }),
+    ]);
+    assert_eq!(Template::parse("#{n}").unwrap().segments, vec![
+        Segment::Marker(Marker {
+            capture: Capture::Counter,
+            position: 0,
+            modifier: Modifier::None,
+            filters: vec![],
+        }),
+    ]);
+    assert_eq!(Template::parse("ep#{1/0}").unwrap_err(),
+               "mmv: Invalid destination pattern: division by zero\n    ep#{1/0}\n         ^");
+    assert_eq!(Template::parse("ep#{1+}").unwrap_err(),
+               "mmv: Invalid destination pattern: expected a number\n    ep#{1+}\n          ^");
+}
+
+#[test]
+fn test_render_template_arithmetic() {
+    let tokens = Tokens::empty();
+    let captures = GenericParts {
+        positional: vec!["07".to_string(), "x".to_string()],
+        named: BTreeMap::new(),
+    };
+    let file = SourceFile {
+        path: Path::new("s1e07x.mkv"),
+        captures: &captures,
+        root: Path::new(""),
+        counter: 4,
+    };
+    let render = |pattern: &str| {
+        let template = Template::parse(pattern).unwrap();
+        template.validate(&[None, None], &tokens).unwrap();
+        template.render(&file, &tokens)
+    };
+    assert_eq!(render("e#{1+1:02}").unwrap(), Resolved::Value("e08".to_string()));
+    assert_eq!(render("#{1-2*3}|#{1*10}|#{1:3}").unwrap(), Resolved::Value("1|70|  7".to_string()));
+    assert_eq!(render("#{n:03}_#{n-1}").unwrap(), Resolved::Value("004_3".to_string()));
+    assert_eq!(render("#{2+1}").unwrap_err(),
+               "mmv: Not able to compute #2 for s1e07x.mkv: 'x' is not a number");
+}
+
+#[test]
+fn test_parse_template_filters() {
+    assert_eq!(Template::parse("#{1|date:%d-%m-%Y:%Y|x}").unwrap().segments, vec![
+        Segment::Marker(Marker {
+            capture: Capture::Index(1),
+            position: 0,
+            modifier: Modifier::None,
+            filters: vec![
+                Filter { name: "date".to_string(), argument: Some("%d-%m-%Y:%Y".to_string()), position: 3 },
+                Filter { name: "x".to_string(), argument: None, position: 20 },
+            ],
+        }),
+    ]);
+    assert_eq!(Template::parse("#{1|}").unwrap_err(),
+               "mmv: Invalid destination pattern: expected a filter name\n    #{1|}\n        ^");
+    let tokens = Tokens::default();
+    assert_eq!(Template::parse("#{1|dat}").unwrap().validate(&[None], &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: unknown filter 'dat'\n    #{1|dat}\n       ^");
+    assert_eq!(Template::parse("#{1|date:%Q}").unwrap().validate(&[None], &tokens).unwrap_err(),
+               "mmv: Invalid destination pattern: invalid date format '%Q'\n    #{1|date:%Q}\n       ^");
+}
+
+#[test]
+fn test_render_template_filters() {
+    let tokens = Tokens::default();
+    let captures = GenericParts {
+        positional: vec!["report".to_string(), "07-11-2023".to_string()],
+        named: BTreeMap::new(),
+    };
+    let file = SourceFile {
+        path: Path::new("report_07-11-2023.pdf"),
+        captures: &captures,
+        root: Path::new(""),
+        counter: 1,
+    };
+    let template = Template::parse("#{2|date:%d-%m-%Y:%Y-%m-%d}_#1.pdf").unwrap();
+    template.validate(&[None, None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap(),
+               Resolved::Value("2023-11-07_report.pdf".to_string()));
+    let template = Template::parse("#{1|date:%d-%m-%Y}").unwrap();
+    template.validate(&[None, None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap_err(),
+               "mmv: Not able to apply |date:%d-%m-%Y to #1 for report_07-11-2023.pdf: 'report' is not a date in the format '%d-%m-%Y'");
+}
+
+#[test]
+fn test_render_template_name_filters() {
+    let tokens = Tokens::default();
+    let captures = GenericParts { positional: vec!["Отчёт за май".to_string()], named: BTreeMap::new() };
+    let file = SourceFile { path: Path::new("a"), captures: &captures, root: Path::new(""), counter: 1 };
+    let template = Template::parse("Архив/#1.PDF").unwrap()
+        .with_name_filters(vec![Filter::parse("slug").unwrap()]);
+    template.validate(&[None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap(),
+               Resolved::Value("Архив/otchyot-za-maj.pdf".to_string()));
+    let template = Template::parse("#1").unwrap()
+        .with_name_filters(vec![Filter::parse("translit:icao").unwrap()]);
+    template.validate(&[None], &tokens).unwrap();
+    assert_eq!(template.render(&file, &tokens).unwrap(),
+               Resolved::Value("Otchet za mai".to_string()));
+    assert_eq!(Template::parse("#1").unwrap()
+                   .with_name_filters(vec![Filter::parse("slug:x").unwrap()])
+                   .validate(&[None], &tokens).unwrap_err(),
+               "mmv: Invalid name filter '|slug:x': unknown transliteration table 'x', expected 'gost', 'iso9' or 'icao'");
+    assert!(Filter::parse("sl ug").is_err());
+}
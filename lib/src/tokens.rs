@@ -0,0 +1,194 @@
+use crate::audio_tags::AudioTagProvider;
+use crate::content_hash::ContentHashProvider;
+use crate::date_filter::DateFilter;
+use crate::exif_metadata::ExifProvider;
+use crate::file_metadata::FileMetadataProvider;
+use crate::path_context::PathContextProvider;
+use crate::template::{Filter, SourceFile, Token};
+use crate::transliteration::TranslitFilter;
+use crate::value_map::MapFilter;
+
+/// The outcome of resolving a token, or a whole destination, for one source file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolved {
+    /// The resolved text.
+    Value(String),
+    /// The file has no value for a token and is left where it is, for the given reason.
+    Skip(String),
+}
+
+/// Number Format
+///
+/// Width and padding of a numeric value: `02` pads to two digits with zeros, `3` pads to three
+/// characters with spaces.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub width: usize,
+    pub zero_pad: bool,
+}
+
+impl NumberFormat {
+    /// Parses a format such as `02` or `3`.
+    pub fn parse(spec: &str) -> Result<NumberFormat, String> {
+        if spec.is_empty() || !spec.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("invalid number format '{}', expected a width such as '02'", spec));
+        }
+        let width = spec.parse().map_err(|_| format!("number width '{}' is too large", spec))?;
+        Ok(NumberFormat { width, zero_pad: spec.starts_with('0') })
+    }
+
+    /// Formats a number with this width and padding.
+    pub fn format(&self, value: i64) -> String {
+        if self.zero_pad {
+            format!("{:0width$}", value, width = self.width)
+        } else {
+            format!("{:>width$}", value, width = self.width)
+        }
+    }
+}
+
+/// Sanitize Value
+///
+/// Makes a value read from file contents safe to use as a single path component: path
+/// separators and control characters are replaced with `_`, surrounding whitespace is trimmed,
+/// and `.` and `..` are replaced with `_`.
+///
+pub fn sanitize_value(value: &str) -> String {
+    let sanitized: String = value.trim()
+        .chars()
+        .map(|c| if c == '/' || c.is_control() { '_' } else { c })
+        .collect();
+    match sanitized.as_str() {
+        "." | ".." => "_".to_string(),
+        _ => sanitized,
+    }
+}
+
+/// Token Provider
+///
+/// Resolves `{name}` and `{name:argument}` tokens of a destination template for a source file.
+///
+/// A provider is asked to validate every token it provides once, when the template is checked,
+/// and then to resolve it for each matched file.
+///
+pub trait TokenProvider: Sync {
+    /// Returns `true` if this provider resolves tokens called `name`.
+    fn provides(&self, name: &str) -> bool;
+
+    /// Checks the argument of a token before any file is touched.
+    fn validate(&self, token: &Token) -> Result<(), String>;
+
+    /// Resolves a token for a source file.
+    fn resolve(&self, token: &Token, file: &SourceFile) -> Result<Resolved, String>;
+}
+
+/// Filter Provider
+///
+/// Transforms the value of a marker through a `|name` or `|name:argument` filter, as in
+/// `#{1|date:%d-%m-%Y:%Y-%m-%d}`.
+///
+/// Like a token provider, a filter provider validates every filter it provides once, when the
+/// template is checked, and then applies it to the marker value of each matched file.
+///
+pub trait FilterProvider: Sync {
+    /// Returns `true` if this provider applies filters called `name`.
+    fn provides(&self, name: &str) -> bool;
+
+    /// Checks the argument of a filter before any file is touched.
+    fn validate(&self, filter: &Filter) -> Result<(), String>;
+
+    /// Applies a filter to a marker value.
+    fn apply(&self, filter: &Filter, value: &str) -> Result<Resolved, String>;
+}
+
+/// Tokens
+///
+/// The set of token and filter providers available to destination templates.
+///
+pub struct Tokens {
+    providers: Vec<Box<dyn TokenProvider>>,
+    filters: Vec<Box<dyn FilterProvider>>,
+}
+
+impl Tokens {
+    /// Creates an empty set of providers, in which every token and filter is unknown.
+    pub fn empty() -> Tokens {
+        Tokens { providers: Vec::new(), filters: Vec::new() }
+    }
+
+    /// Adds a provider. Providers added later take precedence over earlier ones.
+    pub fn with_provider(mut self, provider: Box<dyn TokenProvider>) -> Tokens {
+        self.providers.insert(0, provider);
+        self
+    }
+
+    /// Returns the provider for tokens called `name`, if any.
+    pub fn find(&self, name: &str) -> Option<&dyn TokenProvider> {
+        self.providers.iter()
+            .find(|provider| provider.provides(name))
+            .map(|provider| provider.as_ref())
+    }
+
+    /// Adds a filter provider. Providers added later take precedence over earlier ones.
+    pub fn with_filter(mut self, filter: Box<dyn FilterProvider>) -> Tokens {
+        self.filters.insert(0, filter);
+        self
+    }
+
+    /// Returns the provider for filters called `name`, if any.
+    pub fn find_filter(&self, name: &str) -> Option<&dyn FilterProvider> {
+        self.filters.iter()
+            .find(|filter| filter.provides(name))
+            .map(|filter| filter.as_ref())
+    }
+}
+
+impl Default for Tokens {
+    /// Creates the set of built-in providers.
+    fn default() -> Tokens {
+        Tokens::empty()
+            .with_provider(Box::new(FileMetadataProvider))
+            .with_provider(Box::new(ExifProvider::default()))
+            .with_provider(Box::new(AudioTagProvider::default()))
+            .with_provider(Box::new(ContentHashProvider::default()))
+            .with_provider(Box::new(PathContextProvider))
+            .with_filter(Box::new(DateFilter))
+            .with_filter(Box::new(TranslitFilter::default()))
+            .with_filter(Box::new(MapFilter::default()))
+    }
+}
+
+
+#[test]
+fn test_find_provider() {
+    let tokens = Tokens::default();
+    assert!(tokens.find("mtime").is_some());
+    assert!(tokens.find("exif").is_some());
+    assert!(tokens.find("tag").is_some());
+    assert!(tokens.find("blake3").is_some());
+    assert!(tokens.find("relpath").is_some());
+    assert!(tokens.find("unknown").is_none());
+    assert!(Tokens::empty().find("mtime").is_none());
+    assert!(tokens.find_filter("date").is_some());
+    assert!(tokens.find_filter("slug").is_some());
+    assert!(tokens.find_filter("map").is_some());
+    assert!(tokens.find_filter("unknown").is_none());
+}
+
+#[test]
+fn test_number_format() {
+    assert_eq!(NumberFormat::parse("02").unwrap().format(3), "03");
+    assert_eq!(NumberFormat::parse("3").unwrap().format(7), "  7");
+    assert_eq!(NumberFormat::parse("03").unwrap().format(-4), "-04");
+    assert_eq!(NumberFormat::parse("02").unwrap().format(123), "123");
+    assert!(NumberFormat::parse("x2").is_err());
+    assert!(NumberFormat::parse("").is_err());
+}
+
+#[test]
+fn test_sanitize_value() {
+    assert_eq!(sanitize_value(" AC/DC "), "AC_DC");
+    assert_eq!(sanitize_value("line\nbreak"), "line_break");
+    assert_eq!(sanitize_value(".."), "_");
+}
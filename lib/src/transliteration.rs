@@ -0,0 +1,212 @@
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::template::Filter;
+use crate::tokens::{FilterProvider, Resolved};
+
+/// Cyrillic letters covered by the transliteration tables, in the order of their columns.
+const LETTERS: [char; 37] = [
+    'а', 'б', 'в', 'г', 'д', 'е', 'ё', 'ж', 'з', 'и', 'й', 'к', 'л', 'м', 'н', 'о', 'п', 'р', 'с',
+    'т', 'у', 'ф', 'х', 'ц', 'ч', 'ш', 'щ', 'ъ', 'ы', 'ь', 'э', 'ю', 'я', 'і', 'ї', 'є', 'ґ',
+];
+
+/// GOST 7.79-2000, system B: ASCII only, with `` ` `` marking the hard and soft signs.
+const GOST: [&str; 37] = [
+    "a", "b", "v", "g", "d", "e", "yo", "zh", "z", "i", "j", "k", "l", "m", "n", "o", "p", "r", "s",
+    "t", "u", "f", "x", "cz", "ch", "sh", "shh", "``", "y`", "`", "e`", "yu", "ya", "i", "yi", "ye",
+    "g`",
+];
+
+/// ISO 9:1995 (GOST 7.79-2000, system A): one Latin letter per Cyrillic letter, with diacritics.
+const ISO9: [&str; 37] = [
+    "a", "b", "v", "g", "d", "e", "ë", "ž", "z", "i", "j", "k", "l", "m", "n", "o", "p", "r", "s",
+    "t", "u", "f", "h", "c", "č", "š", "ŝ", "ʺ", "y", "ʹ", "è", "û", "â", "ì", "ï", "ê", "g̀",
+];
+
+/// ICAO Doc 9303, as used in passports: ASCII only, without marks for the hard and soft signs.
+const ICAO: [&str; 37] = [
+    "a", "b", "v", "g", "d", "e", "e", "zh", "z", "i", "i", "k", "l", "m", "n", "o", "p", "r", "s",
+    "t", "u", "f", "kh", "ts", "ch", "sh", "shch", "ie", "y", "", "e", "iu", "ia", "i", "i", "ie",
+    "g",
+];
+
+/// A table for transliterating Cyrillic to Latin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranslitTable {
+    /// GOST 7.79-2000, system B.
+    #[default]
+    Gost,
+    /// ISO 9:1995.
+    Iso9,
+    /// ICAO Doc 9303.
+    Icao,
+}
+
+impl FromStr for TranslitTable {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<TranslitTable, String> {
+        match value {
+            "gost" => Ok(TranslitTable::Gost),
+            "iso9" => Ok(TranslitTable::Iso9),
+            "icao" => Ok(TranslitTable::Icao),
+            _ => Err(format!("mmv: Unknown transliteration table '{}', expected 'gost', 'iso9' or 'icao'",
+                             value)),
+        }
+    }
+}
+
+impl TranslitTable {
+    fn column(self) -> &'static [&'static str; 37] {
+        match self {
+            TranslitTable::Gost => &GOST,
+            TranslitTable::Iso9 => &ISO9,
+            TranslitTable::Icao => &ICAO,
+        }
+    }
+}
+
+/// Transliterate
+///
+/// Transliterates text to Latin: Cyrillic letters with the given table, keeping the case of
+/// the first letter (`Щука` becomes `Shhuka` with GOST), or of the whole word if it is written
+/// in capitals (`ЩУКА` becomes `SHHUKA`), and letters of other scripts with their closest ASCII
+/// spelling. ASCII characters are kept as they are.
+///
+pub fn transliterate(text: &str, table: TranslitTable) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let is_capital = |index: Option<usize>| index
+        .and_then(|index| chars.get(index))
+        .is_some_and(|c| c.is_alphabetic() && c.is_uppercase());
+    let mut latin = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if c.is_ascii() {
+            latin.push(c);
+            continue;
+        }
+        let lowercase = c.to_lowercase().next().unwrap_or(c);
+        match LETTERS.iter().position(|letter| *letter == lowercase) {
+            Some(letter) => {
+                let mut letters = table.column()[letter].chars();
+                // A capital followed by a capital, or ending a word after one, is part of a word in capitals
+                let next_is_letter = chars.get(index + 1).is_some_and(|next| next.is_alphabetic());
+                let in_capitals = is_capital(Some(index + 1))
+                    || (!next_is_letter && is_capital(index.checked_sub(1)));
+                if c != lowercase && in_capitals {
+                    latin.extend(letters.flat_map(char::to_uppercase));
+                    continue;
+                }
+                if c != lowercase {
+                    latin.extend(letters.next().into_iter().flat_map(char::to_uppercase));
+                }
+                latin.extend(letters);
+            }
+            None => latin.push_str(any_ascii::any_ascii_char(c)),
+        }
+    }
+    latin
+}
+
+/// Slugify
+///
+/// Makes text safe for URLs and web uploads: transliterates it to ASCII, lowercases it, drops
+/// apostrophes and the GOST marks for the hard and soft signs, and collapses every other run of
+/// characters except letters and digits into a single `-`. The slug is empty if the text has
+/// no letters or digits, which `TranslitFilter` reports as an error.
+///
+pub fn slugify(text: &str, table: TranslitTable) -> String {
+    let ascii = any_ascii::any_ascii(&transliterate(text, table)).to_lowercase();
+    let mut slug = String::new();
+    for c in ascii.chars().filter(|c| !matches!(c, '\'' | '`' | '"')) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Transliteration Filter
+///
+/// Applies the `|translit` and `|slug` filters. The table is chosen per filter, as in
+/// `#{1|slug:icao}`, and otherwise defaults to the configured one.
+///
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TranslitFilter {
+    pub table: TranslitTable,
+}
+
+impl TranslitFilter {
+    pub fn new(table: TranslitTable) -> TranslitFilter {
+        TranslitFilter { table }
+    }
+
+    fn table(&self, filter: &Filter) -> Result<TranslitTable, String> {
+        match filter.argument.as_deref() {
+            Some(name) => name.parse()
+                .map_err(|_| format!("unknown transliteration table '{}', expected 'gost', 'iso9' or 'icao'",
+                                     name)),
+            None => Ok(self.table),
+        }
+    }
+}
+
+impl FilterProvider for TranslitFilter {
+    fn provides(&self, name: &str) -> bool {
+        matches!(name, "translit" | "slug")
+    }
+
+    fn validate(&self, filter: &Filter) -> Result<(), String> {
+        self.table(filter).map(|_| ())
+    }
+
+    fn apply(&self, filter: &Filter, value: &str) -> Result<Resolved, String> {
+        let table = self.table(filter)?;
+        match filter.name.as_str() {
+            "slug" => match slugify(value, table) {
+                slug if slug.is_empty() && !value.is_empty() =>
+                    Err(format!("nothing is left of '{}', it has no letters or digits", value)),
+                slug => Ok(Resolved::Value(slug)),
+            },
+            _ => Ok(Resolved::Value(transliterate(value, table))),
+        }
+    }
+}
+
+
+#[test]
+fn test_transliterate() {
+    assert_eq!(transliterate("Щука и ёж", TranslitTable::Gost), "Shhuka i yozh");
+    assert_eq!(transliterate("Щука и ёж", TranslitTable::Iso9), "Ŝuka i ëž");
+    assert_eq!(transliterate("Щука и ёж", TranslitTable::Icao), "Shchuka i ezh");
+    assert_eq!(transliterate("Объявление.txt", TranslitTable::Gost), "Ob``yavlenie.txt");
+    assert_eq!(transliterate("Café Ελλάδα", TranslitTable::Gost), "Cafe Ellada");
+    assert_eq!(transliterate("ЩУКА и ЁЖ.", TranslitTable::Gost), "SHHUKA i YOZH.");
+    assert_eq!(transliterate("Щ ЩУКА Щука Я", TranslitTable::Icao), "Shch SHCHUKA Shchuka Ia");
+}
+
+#[test]
+fn test_slugify() {
+    assert_eq!(slugify("Отчёт за Май (финал)", TranslitTable::Gost), "otchyot-za-maj-final");
+    assert_eq!(slugify("Объявление Высылки", TranslitTable::Gost), "obyavlenie-vysylki");
+    assert_eq!(slugify("Щука", TranslitTable::Iso9), "suka");
+    assert_eq!(slugify("  --Hello, World!--  ", TranslitTable::Icao), "hello-world");
+    assert_eq!(slugify("???", TranslitTable::Gost), "");
+}
+
+#[test]
+fn test_translit_filter() {
+    let provider = TranslitFilter::new(TranslitTable::Icao);
+    assert!(provider.validate(&Filter::new("slug", Some("gost"))).is_ok());
+    assert!(provider.validate(&Filter::new("translit", Some("bgn"))).is_err());
+    assert_eq!(provider.apply(&Filter::new("translit", None), "Юля").unwrap(),
+               Resolved::Value("Iulia".to_string()));
+    assert_eq!(provider.apply(&Filter::new("translit", Some("gost")), "Юля").unwrap(),
+               Resolved::Value("Yulya".to_string()));
+    assert_eq!(provider.apply(&Filter::new("slug", None), "Юля Щ").unwrap(),
+               Resolved::Value("iulia-shch".to_string()));
+    assert_eq!(provider.apply(&Filter::new("slug", None), "???").unwrap_err(),
+               "nothing is left of '???', it has no letters or digits");
+    assert_eq!(provider.apply(&Filter::new("slug", None), "").unwrap(), Resolved::Value(String::new()));
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::template::Filter;
+use crate::tokens::{FilterProvider, Resolved};
+
+/// What to do with a marker value that is missing from a map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingKey {
+    /// Leave the file where it is.
+    Skip,
+    /// Use the value itself.
+    Keep,
+    /// Fail the whole run.
+    #[default]
+    Error,
+}
+
+impl FromStr for MissingKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<MissingKey, String> {
+        match value {
+            "skip" => Ok(MissingKey::Skip),
+            "keep" => Ok(MissingKey::Keep),
+            "error" => Ok(MissingKey::Error),
+            _ => Err(format!("mmv: Unknown missing key policy '{}', expected 'skip', 'keep' or 'error'",
+                             value)),
+        }
+    }
+}
+
+/// Split Record
+///
+/// Splits one line of a map file into fields. Tab-separated lines are split on tabs as they
+/// are; otherwise fields are separated by commas and may be quoted with `"`, a doubled `""`
+/// standing for a quote inside a quoted field.
+///
+pub(crate) fn split_record(line: &str, tab_separated: bool) -> Result<Vec<String>, String> {
+    if tab_separated {
+        return Ok(line.split('\t').map(|field| field.to_string()).collect());
+    }
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unclosed quote".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Load Map
+///
+/// Reads a two-column map file: a key and its replacement on every line. Files ending in `.tsv`
+/// are tab-separated, all others comma-separated. Empty lines are ignored, and so are columns
+/// after the second one.
+///
+/// # Arguments
+///
+/// * `path` - The map file.
+///
+/// # Returns
+///
+/// The replacement of every key, or an error naming the line of a malformed or duplicate entry.
+///
+pub fn load_map(path: &Path) -> Result<HashMap<String, String>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("mmv: Not able to read map {}: {}", path.to_string_lossy(), e))?;
+    let tab_separated = path.extension().is_some_and(|extension| extension == "tsv");
+    let mut map = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("mmv: Invalid map {} line {}: {}",
+                                            path.to_string_lossy(), number + 1, message);
+        let fields = split_record(line, tab_separated).map_err(|message| error(&message))?;
+        let (key, value) = match fields.as_slice() {
+            [key, value, ..] => (key.trim().to_string(), value.trim().to_string()),
+            _ => return Err(error("expected a key and a value")),
+        };
+        if map.contains_key(&key) {
+            return Err(error(&format!("duplicate key '{}'", key)));
+        }
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+/// Map Filter
+///
+/// Applies `|map:name` filters, replacing a marker value with its entry in the map loaded
+/// under `name`, e.g. `#{1|map:customers}` for a `customers` map from customer IDs to names.
+/// A value missing from the map is handled according to the configured `MissingKey` policy.
+///
+#[derive(Debug, Clone, Default)]
+pub struct MapFilter {
+    maps: HashMap<String, HashMap<String, String>>,
+    pub missing: MissingKey,
+}
+
+impl MapFilter {
+    pub fn new(missing: MissingKey) -> MapFilter {
+        MapFilter { maps: HashMap::new(), missing }
+    }
+
+    /// Adds a map that filters refer to by `name`.
+    pub fn with_map(mut self, name: &str, map: HashMap<String, String>) -> MapFilter {
+        self.maps.insert(name.to_string(), map);
+        self
+    }
+
+    /// With Map File
+    ///
+    /// Loads a map given as `name=path`, as in `--map customers=customers.csv`.
+    ///
+    pub fn with_map_file(self, spec: &str) -> Result<MapFilter, String> {
+        let (name, path) = spec.split_once('=')
+            .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+            .ok_or_else(|| format!("mmv: Invalid map '{}', expected name=file.csv", spec))?;
+        let map = load_map(Path::new(path))?;
+        Ok(self.with_map(name, map))
+    }
+
+    /// Returns the name of the map a filter refers to, and the map.
+    fn map<'a>(&'a self,
+               filter: &'a Filter) -> Result<(&'a str, &'a HashMap<String, String>), String> {
+        let name = filter.argument.as_deref()
+            .ok_or_else(|| "expected a map name, e.g. |map:customers".to_string())?;
+        let map = self.maps.get(name)
+            .ok_or_else(|| format!("unknown map '{}', load it with --map {}=file.csv", name, name))?;
+        Ok((name, map))
+    }
+}
+
+impl FilterProvider for MapFilter {
+    fn provides(&self, name: &str) -> bool {
+        name == "map"
+    }
+
+    fn validate(&self, filter: &Filter) -> Result<(), String> {
+        self.map(filter).map(|_| ())
+    }
+
+    fn apply(&self, filter: &Filter, value: &str) -> Result<Resolved, String> {
+        let (name, map) = self.map(filter)?;
+        match map.get(value) {
+            Some(replacement) => Ok(Resolved::Value(replacement.clone())),
+            None => match self.missing {
+                MissingKey::Skip => Ok(Resolved::Skip(format!("no '{}' in map {}", value, name))),
+                MissingKey::Keep => Ok(Resolved::Value(value.to_string())),
+                MissingKey::Error => Err(format!("no '{}' in map {}", value, name)),
+            },
+        }
+    }
+}
+
+
+#[test]
+fn test_load_map() {
+    let temp_dir = tempdir::TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let csv = temp_dir.path().join("customers.csv");
+    fs::write(&csv, "17,Acme\r\n\n\"42\", \"Smith, \"\"Jr\"\"\",extra\n").expect("Failed to write file");
+    let map = load_map(&csv).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map["17"], "Acme");
+    assert_eq!(map["42"], "Smith, \"Jr\"");
+
+    let tsv = temp_dir.path().join("customers.tsv");
+    fs::write(&tsv, "17\tAcme, Inc\n").expect("Failed to write file");
+    assert_eq!(load_map(&tsv).unwrap()["17"], "Acme, Inc");
+
+    fs::write(&csv, "17,Acme\n17,Other\n").expect("Failed to write file");
+    assert!(load_map(&csv).unwrap_err().ends_with("line 2: duplicate key '17'"));
+    fs::write(&csv, "17\n").expect("Failed to write file");
+    assert!(load_map(&csv).unwrap_err().ends_with("line 1: expected a key and a value"));
+}
+
+#[test]
+fn test_map_filter() {
+    let filter = |argument: &str| Filter::new("map", Some(argument));
+    let map = HashMap::from([("17".to_string(), "Acme".to_string())]);
+    let provider = MapFilter::default().with_map("customers", map.clone());
+    assert!(provider.validate(&filter("customers")).is_ok());
+    assert!(provider.validate(&filter("clients")).is_err());
+    assert_eq!(provider.apply(&filter("customers"), "17").unwrap(),
+               Resolved::Value("Acme".to_string()));
+    assert_eq!(provider.apply(&filter("customers"), "18").unwrap_err(), "no '18' in map customers");
+
+    let provider = MapFilter::new(MissingKey::Skip).with_map("customers", map.clone());
+    assert_eq!(provider.apply(&filter("customers"), "18").unwrap(),
+               Resolved::Skip("no '18' in map customers".to_string()));
+    let provider = MapFilter::new(MissingKey::Keep).with_map("customers", map);
+    assert_eq!(provider.apply(&filter("customers"), "18").unwrap(),
+               Resolved::Value("18".to_string()));
+}
@@ -0,0 +1,381 @@
+use mmove::curation::{self, Curation};
+use mmove::exif_metadata::ExifFallback;
+use mmove::mass_move::MassMove;
+use mmove::move_plan::{self, Mode, OnConflict, PlanEntry};
+use mmove::reporter::PrintReporter;
+use mmove::transliteration::TranslitTable;
+use mmove::value_map::MissingKey;
+
+use clap::Parser;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::time::{Duration, Instant};
+
+/// Mass Move Files Interactively
+///
+/// A full-screen preview of a mass move: the source and destination patterns are edited at the
+/// top, and the files they match are listed below with their old and new paths, the changed
+/// part of every path highlighted. The list is planned again, with the same checks as `mmv`,
+/// once typing pauses, and single files can be left out before the moves are carried out.
+///
+/// # Keys
+///
+/// * `Tab`, `Shift-Tab` - Switch between the source pattern, the destination pattern and the list.
+/// * `Up`, `Down`, `PageUp`, `PageDown`, `Home`, `End` - Select a file in the list.
+/// * `Space` - Leave the selected file out, or put it back in, when the list is active.
+/// * `Enter` - Carry out the moves of the files kept, unless they conflict.
+/// * `Esc`, `Ctrl-C` - Quit without moving anything.
+///
+/// # Example
+/// ```
+/// ./mmv-tui 'photos/*.jpg' 'sorted/#1.jpg'
+/// ```
+///
+#[derive(Parser, Debug)]
+/// Command-line arguments for the 'mmv-tui' tool.
+struct Args {
+    /// The initial source pattern, as for `mmv`.
+    #[clap(default_value = "")]
+    pub source_pattern: String,
+    /// The initial destination pattern, as for `mmv`.
+    #[clap(default_value = "")]
+    pub target_pattern: String,
+    /// Force mode: Replace existing files in the destination directory (optional).
+    #[clap(short, long)]
+    pub force: bool,
+    /// What to do with files whose destination already exists: `error`, `skip` or `overwrite`.
+    #[clap(long, value_name = "POLICY", default_value = "error", conflicts_with = "force",
+           value_parser = ["error", "skip", "overwrite"])]
+    pub on_conflict: String,
+    /// Move the files (`move`) or copy them (`copy`).
+    #[clap(long, value_name = "MODE", default_value = "move", value_parser = ["move", "copy"])]
+    pub mode: String,
+    /// What to do with files that lack an EXIF tag: `skip`, `mtime` or `error`.
+    #[clap(long, value_name = "POLICY", default_value = "error",
+           value_parser = ["skip", "mtime", "error"])]
+    pub exif_fallback: String,
+    /// Pass the whole name of every destination file through a filter. May be given several times.
+    #[clap(long, value_name = "FILTER")]
+    pub name_filter: Vec<String>,
+    /// Transliteration table of the `translit` and `slug` filters: `gost`, `iso9` or `icao`.
+    #[clap(long, value_name = "TABLE", default_value = "gost",
+           value_parser = ["gost", "iso9", "icao"])]
+    pub translit_table: String,
+    /// Load a map for `#{1|map:name}` filters, given as `name=file.csv`. May be given several times.
+    #[clap(long, value_name = "NAME=FILE")]
+    pub map: Vec<String>,
+    /// What to do with values missing from a map: `skip`, `keep` or `error`.
+    #[clap(long, value_name = "POLICY", default_value = "error",
+           value_parser = ["skip", "keep", "error"])]
+    pub map_missing: String,
+    /// Report destinations that would not be valid on Windows or macOS as conflicts.
+    #[clap(long)]
+    pub portable: bool,
+}
+
+/// The part of the screen that receives the typed keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Source,
+    Destination,
+    List,
+}
+
+impl Focus {
+    fn next(self) -> Focus {
+        match self {
+            Focus::Source => Focus::Destination,
+            Focus::Destination => Focus::List,
+            Focus::List => Focus::Source,
+        }
+    }
+
+    fn previous(self) -> Focus {
+        self.next().next()
+    }
+}
+
+/// How long typing must pause before the batch is planned again with the edited patterns, so
+/// that the files are not walked and hashed again for every key.
+const REPLAN_DELAY: Duration = Duration::from_millis(300);
+
+/// The state of the screen: the patterns as typed and the batch they plan.
+struct App {
+    curation: Curation,
+    source_pattern: String,
+    destination_pattern: String,
+    /// When the patterns were last edited, if the batch has not been planned with them yet.
+    edited: Option<Instant>,
+    focus: Focus,
+    table: TableState,
+    message: Option<String>,
+}
+
+/// Builds the job from the command-line arguments.
+fn job(args: &Args) -> Result<MassMove, String> {
+    let on_conflict = match args.force {
+        true => OnConflict::Overwrite,
+        false => args.on_conflict.parse::<OnConflict>()?,
+    };
+    let mut job = MassMove::new(&args.source_pattern, &args.target_pattern)
+        .on_conflict(on_conflict)
+        .mode(args.mode.parse::<Mode>()?)
+        .portable(args.portable)
+        .exif_fallback(args.exif_fallback.parse::<ExifFallback>()?)
+        .translit_table(args.translit_table.parse::<TranslitTable>()?)
+        .map_missing(args.map_missing.parse::<MissingKey>()?);
+    for spec in &args.name_filter {
+        job = job.name_filter(spec);
+    }
+    for spec in &args.map {
+        job = job.map(spec);
+    }
+    Ok(job)
+}
+
+impl App {
+    fn new(job: MassMove) -> Result<App, String> {
+        let source_pattern = job.source_pattern().to_string();
+        let destination_pattern = job.destination_pattern().to_string();
+        let focus = match source_pattern.is_empty() {
+            true => Focus::Source,
+            false => Focus::Destination,
+        };
+        let mut app = App {
+            curation: Curation::new(job)?,
+            source_pattern,
+            destination_pattern,
+            edited: None,
+            focus,
+            table: TableState::default(),
+            message: None,
+        };
+        app.clamp_selection();
+        Ok(app)
+    }
+
+    fn selected_entry(&self) -> Option<&PlanEntry> {
+        self.table.selected().and_then(|index| self.curation.entries().get(index))
+    }
+
+    /// Keeps the selection within the list after it changed.
+    fn clamp_selection(&mut self) {
+        let count = self.curation.entries().len();
+        let selected = match (count, self.table.selected()) {
+            (0, _) => None,
+            (_, Some(index)) => Some(index.min(count - 1)),
+            (_, None) => Some(0),
+        };
+        self.table.select(selected);
+    }
+
+    fn edit_pattern(&mut self, edit: impl FnOnce(&mut String)) {
+        match self.focus {
+            Focus::Source => edit(&mut self.source_pattern),
+            Focus::Destination => edit(&mut self.destination_pattern),
+            Focus::List => return,
+        }
+        self.edited = Some(Instant::now());
+    }
+
+    /// How long to wait for a key before the edited patterns are planned, if they are not yet.
+    fn replan_timeout(&self) -> Option<Duration> {
+        self.edited.map(|edited| REPLAN_DELAY.saturating_sub(edited.elapsed()))
+    }
+
+    /// Plans the batch with the patterns as typed, if they were edited since it was planned.
+    fn replan(&mut self) {
+        if self.edited.take().is_some() {
+            self.curation.set_patterns(&self.source_pattern, &self.destination_pattern);
+            self.clamp_selection();
+        }
+    }
+
+    fn select_by(&mut self, offset: isize) {
+        let count = self.curation.entries().len();
+        if count > 0 {
+            let index = self.table.selected().unwrap_or(0).saturating_add_signed(offset);
+            self.table.select(Some(index.min(count - 1)));
+        }
+    }
+
+    /// Handles a key, returning whether the moves are to be carried out, or `None` to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<bool> {
+        self.message = None;
+        // Only typing waits for the patterns to settle; the list is acted on as they are now
+        if !matches!(key.code, KeyCode::Char(_) | KeyCode::Backspace) {
+            self.replan();
+        }
+        match key.code {
+            KeyCode::Esc => return None,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return None,
+            KeyCode::Enter => match self.check() {
+                Ok(()) => return Some(true),
+                Err(message) => self.message = Some(message),
+            },
+            KeyCode::Tab => self.focus = self.focus.next(),
+            KeyCode::BackTab => self.focus = self.focus.previous(),
+            KeyCode::Up => self.select_by(-1),
+            KeyCode::Down => self.select_by(1),
+            KeyCode::PageUp => self.select_by(-10),
+            KeyCode::PageDown => self.select_by(10),
+            KeyCode::Home if self.focus == Focus::List => self.table.select(Some(0)),
+            KeyCode::End if self.focus == Focus::List => self.select_by(isize::MAX),
+            KeyCode::Char(' ') if self.focus == Focus::List => {
+                if let Some(source) = self.selected_entry().map(|entry| entry.source.clone()) {
+                    self.curation.toggle(&source);
+                }
+            }
+            KeyCode::Char(c) => self.edit_pattern(|pattern| pattern.push(c)),
+            KeyCode::Backspace => self.edit_pattern(|pattern| {
+                pattern.pop();
+            }),
+            _ => {}
+        }
+        Some(false)
+    }
+
+    /// Checks that the kept files can be moved as planned.
+    fn check(&self) -> Result<(), String> {
+        if let Some(error) = self.curation.error() {
+            return Err(error.to_string());
+        }
+        let selected = self.curation.selected();
+        if !selected.conflicts.is_empty() {
+            return Err(format!("mmv: {} conflicts left; leave files out or change the patterns",
+                               selected.conflicts.len()));
+        }
+        if selected.entries.is_empty() {
+            return Err("mmv: No files to move".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A path with its changed part highlighted.
+fn highlighted<'a>(path: &'a str, changed: std::ops::Range<usize>, style: Style, highlight: Style) -> Line<'a> {
+    Line::from(vec![
+        Span::styled(&path[..changed.start], style),
+        Span::styled(&path[changed.clone()], style.patch(highlight)),
+        Span::styled(&path[changed.end..], style),
+    ])
+}
+
+fn draw_pattern(frame: &mut Frame, area: Rect, title: &str, pattern: &str, focused: bool) {
+    let border = match focused {
+        true => Style::new().fg(Color::Yellow),
+        false => Style::new(),
+    };
+    let block = Block::new().borders(Borders::ALL).title(title).border_style(border);
+    frame.render_widget(Paragraph::new(pattern).block(block), area);
+    if focused {
+        let width = Line::from(pattern).width() as u16;
+        frame.set_cursor_position((area.x + 1 + width.min(area.width.saturating_sub(3)), area.y + 1));
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let [source_area, destination_area, list_area, problems_area, help_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(3),
+        Constraint::Length(5),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+    draw_pattern(frame, source_area, "Source pattern", &app.source_pattern, app.focus == Focus::Source);
+    draw_pattern(frame, destination_area, "Destination pattern", &app.destination_pattern,
+                 app.focus == Focus::Destination);
+
+    let curation = &app.curation;
+    let rows = curation.entries().iter().map(|entry| {
+        let (old_changed, new_changed) = curation::changed_range(&entry.source, &entry.destination);
+        let (mark, style) = match (curation.is_included(&entry.source), curation.problems(&entry.source).is_empty()) {
+            (false, _) => ("[ ]", Style::new().fg(Color::DarkGray)),
+            (true, true) => ("[x]", Style::new()),
+            (true, false) => ("[!]", Style::new().fg(Color::Red)),
+        };
+        let highlight = Style::new().add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+        Row::new(vec![
+            Line::from(mark).style(style),
+            highlighted(&entry.source, old_changed, style, highlight.fg(Color::Yellow)),
+            highlighted(&entry.destination, new_changed, style, highlight.fg(Color::Green)),
+        ])
+    });
+    let selected = curation.selected();
+    let title = format!("{} of {} files, {} conflicts, {} skipped", selected.entries.len(),
+                        curation.entries().len(), selected.conflicts.len(), selected.skipped.len());
+    let border = match app.focus {
+        Focus::List => Style::new().fg(Color::Yellow),
+        _ => Style::new(),
+    };
+    let table = Table::new(rows, [Constraint::Length(3), Constraint::Fill(1), Constraint::Fill(1)])
+        .header(Row::new(vec!["", "Old", "New"]).style(Style::new().add_modifier(Modifier::BOLD)))
+        .block(Block::new().borders(Borders::ALL).title(title).border_style(border))
+        .column_spacing(1)
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(table, list_area, &mut app.table);
+
+    let problems: Vec<Line> = match (&app.message, app.curation.error()) {
+        (Some(message), _) => vec![Line::styled(message.as_str(), Style::new().fg(Color::Red))],
+        (None, Some(error)) => error.lines().map(|line| Line::styled(line, Style::new().fg(Color::Red))).collect(),
+        (None, None) => app.selected_entry()
+            .map(|entry| app.curation.problems(&entry.source))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|problem| Line::styled(problem, Style::new().fg(Color::Red)))
+            .collect(),
+    };
+    frame.render_widget(Paragraph::new(problems)
+                            .wrap(Wrap { trim: false })
+                            .block(Block::new().borders(Borders::ALL).title("Problems")), problems_area);
+    frame.render_widget(Paragraph::new("Tab: switch  Up/Down: select  Space: leave out  Enter: move  Esc: quit")
+                            .style(Style::new().fg(Color::DarkGray)), help_area);
+}
+
+/// Runs the screen until the moves are confirmed, returning whether they are.
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> Result<bool, String> {
+    loop {
+        terminal.draw(|frame| draw(frame, app)).map_err(|e| format!("mmv: Not able to draw: {}", e))?;
+        if let Some(timeout) = app.replan_timeout() {
+            if !event::poll(timeout).map_err(|e| format!("mmv: Not able to read a key: {}", e))? {
+                app.replan();
+                continue;
+            }
+        }
+        match event::read().map_err(|e| format!("mmv: Not able to read a key: {}", e))? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match app.handle_key(key) {
+                Some(true) => return Ok(true),
+                Some(false) => {}
+                None => return Ok(false),
+            },
+            _ => {}
+        }
+    }
+}
+
+/// The entry point of the 'mmv-tui' tool. Shows the batch until it is confirmed, then carries
+/// out the moves of the files kept through the same engine as `mmv`.
+fn main() {
+    let args = Args::parse();
+    let result = (|| {
+        let mut app = App::new(job(&args)?)?;
+        let mut terminal = ratatui::init();
+        let confirmed = run(&mut terminal, &mut app);
+        ratatui::restore();
+        match confirmed? {
+            true => move_plan::execute(app.curation.selected(), &mut PrintReporter::default()),
+            false => Ok(()),
+        }
+    })();
+    match result {
+        Ok(_) => std::process::exit(0),
+        Err(e) => {
+            eprint!("{}", e);
+            std::process::exit(1)
+        }
+    }
+}
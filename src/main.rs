@@ -1,6 +1,17 @@
-use mmove::mass_move;
+use mmove::edit;
+use mmove::exif_metadata::ExifFallback;
+use mmove::interactive;
+use mmove::mapping::{self, MappingFormat};
+use mmove::mass_move::{MassMove, MoveOptions};
+use mmove::move_plan::{self, Mode, OnConflict};
+use mmove::report_format::{QuotingStyle, ReportFormat};
+use mmove::reporter::{PrintReporter, RecordFormat, RecordReporter, Reporter};
+use mmove::transliteration::TranslitTable;
+use mmove::value_map::MissingKey;
 
 use clap::Parser;
+use std::io::Read;
+use std::path::PathBuf;
 
 /// Mass Move Files
 ///
@@ -14,15 +25,54 @@ use clap::Parser;
 /// * `destination_pattern` - A pattern for the destination path, formed with regular characters
 ///   and special markers like `#1`, `#2`, and so on. These markers indicate which portions
 ///   marked with asterisks in the source file pattern should be inserted into the new filename.
+///   A `{` starts a token such as `{mtime:%Y}`; unlike in earlier versions it is never a
+///   literal brace, which is written as `{{`.
 ///
 /// # Flags
 ///
 /// * `-h`, `--help` - Show help documentation.
 /// * `-f`, `--force` - Overwrite existing files if they exist.
+/// * `--on-conflict` - What to do with files whose destination exists: `error` (default),
+///   `skip` or `overwrite` (the same as `-f`).
+/// * `--mode` - `move` (default) or `copy` the files.
+/// * `-n`, `--dry-run` - Print the moves without moving anything.
+/// * `--exif-fallback` - What to do with files without a requested EXIF tag: `skip`, `mtime` or
+///   `error` (default).
+/// * `--name-filter` - A filter such as `slug` or `translit` applied to every destination file
+///   name; may be repeated.
+/// * `--translit-table` - The default table of the `translit` and `slug` filters: `gost`
+///   (default), `iso9` or `icao`.
+/// * `--map` - A `name=file.csv` map for `#{1|map:name}` filters; may be repeated.
+/// * `--map-missing` - What to do with values missing from a map: `skip`, `keep` or `error`
+///   (default).
+/// * `--portable` - Fail before moving anything unless every destination name is valid on
+///   Windows and macOS.
+/// * `--plan-out` - Write the moves to a JSON plan file, or a CSV one if it ends in `.csv`,
+///   instead of moving anything.
+/// * `--apply` - Carry out a plan file written with `--plan-out`, instead of the patterns.
+/// * `--mapping` - Move files by explicit source and destination pairs read from a file or
+///   stdin (`-`), instead of the patterns.
+/// * `--mapping-format` - How the pairs are written: `tsv`, `csv` or `nul`; by default `csv`
+///   for `.csv` files and `tsv` otherwise.
+/// * `-e`, `--edit` - Rename the files matching the source pattern by editing their list in
+///   `$EDITOR`, instead of a target pattern.
+/// * `-I`, `--interactive` - Ask before every move: `y` moves, `n` skips, `a` moves all
+///   remaining files, `q` quits and `e` edits the destination.
+/// * `--json` - Print a JSON object with the status, source, destination and error of every
+///   file on its own line, instead of `source -> destination`.
+/// * `--print0` - Print the status, source, destination and error of every file, each
+///   terminated by a NUL character, instead of `source -> destination`.
+/// * `--format` - Print every file as a format such as `{src}\t{dst}\t{size}` instead of
+///   `source -> destination`.
+/// * `--quoting-style` - Quote the paths printed: `literal` (default), `shell` or `c`.
 ///
 /// # Example
 /// ```
 /// ./mmv 'source_pattern' 'target_pattern' --force(optional)
+/// ./mmv 'source_pattern' 'target_pattern' --plan-out plan.json
+/// ./mmv --apply plan.json
+/// ./mmv --mapping renames.csv
+/// ./mmv 'source_pattern' --edit
 /// ```
 ///
 #[derive(Parser, Debug)]
@@ -30,25 +80,216 @@ use clap::Parser;
 struct Args {
     ///   A pattern for selecting files, containing path, name, and the `*` character
     ///   to represent a substring of any length (including an empty string). The `*` character can
-    ///   only appear in the filename.
-    pub source_pattern: String,
+    ///   only appear in the filename, and can be named as `*{name}`.
+    #[clap(required_unless_present_any = ["apply", "mapping"])]
+    pub source_pattern: Option<String>,
     ///  A pattern for the destination path, formed with regular characters
     ///   and special markers like `#1`, `#2`, and so on. These markers indicate which portions
     ///   marked with asterisks in the source file pattern should be inserted into the new filename.
-    pub target_pattern: String,
+    ///   `#{12}` and `#{name}` refer to a capture by number or by name, `#{n}` to the position
+    ///   of the file, and `#{1+1:02}` computes and pads a number.
+    #[clap(required_unless_present_any = ["apply", "mapping", "edit"])]
+    pub target_pattern: Option<String>,
     /// Force mode: Replace existing files in the destination directory (optional).
     #[clap(short, long)]
     pub force: bool,
+    /// What to do with files whose destination already exists: fail before moving anything
+    /// (`error`), leave them where they are (`skip`) or replace the destination (`overwrite`,
+    /// the same as `-f`).
+    #[clap(long, value_name = "POLICY", default_value = "error", conflicts_with = "force",
+           value_parser = ["error", "skip", "overwrite"])]
+    pub on_conflict: String,
+    /// Move the files (`move`) or copy them, leaving the originals in place (`copy`).
+    #[clap(long, value_name = "MODE", default_value = "move", value_parser = ["move", "copy"])]
+    pub mode: String,
+    /// Print what would be moved without moving anything.
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+    /// What to do with files that lack an EXIF tag used in the destination pattern: leave them
+    /// where they are (`skip`), use their modification time for date tags and skip them for
+    /// others (`mtime`), or fail (`error`).
+    #[clap(long, value_name = "POLICY", default_value = "error",
+           value_parser = ["skip", "mtime", "error"])]
+    pub exif_fallback: String,
+    /// Pass the whole name of every destination file through a filter, e.g. `slug` for
+    /// ASCII-safe names or `translit:iso9`. May be given several times.
+    #[clap(long, value_name = "FILTER")]
+    pub name_filter: Vec<String>,
+    /// Transliteration table used by the `translit` and `slug` filters unless one is given:
+    /// GOST 7.79-2000 system B (`gost`), ISO 9 (`iso9`) or ICAO Doc 9303 (`icao`).
+    #[clap(long, value_name = "TABLE", default_value = "gost",
+           value_parser = ["gost", "iso9", "icao"])]
+    pub translit_table: String,
+    /// Load a two-column CSV (or `.tsv`) file as a map for `#{1|map:name}` filters, given as
+    /// `name=file.csv`. May be given several times.
+    #[clap(long, value_name = "NAME=FILE")]
+    pub map: Vec<String>,
+    /// What to do with files whose marker value is missing from a map: leave them where they are
+    /// (`skip`), use the value itself (`keep`) or fail (`error`).
+    #[clap(long, value_name = "POLICY", default_value = "error",
+           value_parser = ["skip", "keep", "error"])]
+    pub map_missing: String,
+    /// Fail before moving anything if a destination would not be valid on Windows or macOS:
+    /// reserved names such as `CON`, characters such as `:` or `?`, names ending with a dot or
+    /// a space, and destinations differing only in case.
+    #[clap(long)]
+    pub portable: bool,
+    /// Write the planned moves, with the inode, size and modification time of every source
+    /// file, to a JSON file for review instead of moving anything; to a CSV file if the name ends
+    /// in `.csv`.
+    #[clap(long, value_name = "FILE")]
+    pub plan_out: Option<PathBuf>,
+    /// Carry out the moves of a plan written with `--plan-out`, after checking that no source
+    /// file has changed since, that no destination conflicts and that destinations edited by
+    /// hand pass the same checks as planned ones.
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["source_pattern", "target_pattern", "plan_out"])]
+    pub apply: Option<PathBuf>,
+    /// Move files by explicit pairs of a source and a destination path, one pair per line,
+    /// read from a file or from stdin with `-`, instead of the patterns.
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["source_pattern", "target_pattern", "apply"])]
+    pub mapping: Option<PathBuf>,
+    /// How the pairs of `--mapping` are written: separated by a tab (`tsv`), by a comma
+    /// (`csv`), or alternating and terminated by NUL characters (`nul`).
+    #[clap(long, value_name = "FORMAT", value_parser = ["tsv", "csv", "nul"])]
+    pub mapping_format: Option<String>,
+    /// Open the list of files matching the source pattern in `$VISUAL` or `$EDITOR` and rename
+    /// every file whose line was changed, instead of using a target pattern. Lines must not be
+    /// added, removed or emptied.
+    #[clap(short, long, conflicts_with_all = ["target_pattern", "apply", "mapping"])]
+    pub edit: bool,
+    /// Ask `src -> dst? [y/n/a/q/e]` before every move: move the file (`y`), leave it (`n`),
+    /// move it and all remaining ones (`a`), leave it and all remaining ones (`q`), or edit its
+    /// destination (`e`), which is checked again before asking anew.
+    #[clap(short = 'I', long)]
+    pub interactive: bool,
+    /// Print a JSON object per line for every moved file, or every planned one with `--dry-run`,
+    /// and for every skipped, conflicting or failed one: `status` (`completed`, `planned`,
+    /// `skipped`, `conflict`, `failed` or `error`), `source`, `destination` and `error`.
+    #[clap(long)]
+    pub json: bool,
+    /// Print the same fields as `--json` for every file, each terminated by a NUL character,
+    /// with empty fields for the missing ones.
+    #[clap(long = "print0", short = '0', conflicts_with = "json")]
+    pub print0: bool,
+    /// Print every moved file, or every planned one with `--dry-run`, as FORMAT: `{src}`, `{dst}`
+    /// and `{size}` are the source, the destination and the size in bytes, `\t`, `\n` and `\0`
+    /// a tab, a line break and a NUL character, and `{{` and `}}` braces.
+    #[clap(long, value_name = "FORMAT", conflicts_with_all = ["json", "print0"])]
+    pub format: Option<String>,
+    /// Quote the printed paths as they are (`literal`), for pasting into a shell (`shell`), or
+    /// as C strings (`c`), like `ls --quoting-style`.
+    #[clap(long, value_name = "STYLE", default_value = "literal", conflicts_with_all = ["json", "print0"],
+           value_parser = ["literal", "shell", "c"])]
+    pub quoting_style: String,
+}
+
+/// Reads the pairs of an explicit mapping from a file, or from stdin for `-`.
+fn read_mapping(path: &PathBuf, format: Option<&str>) -> Result<Vec<(String, String)>, String> {
+    let format = match format {
+        Some(format) => format.parse::<MappingFormat>()?,
+        None => MappingFormat::of(path),
+    };
+    let mut text = String::new();
+    let result = match path.to_str() {
+        Some("-") => std::io::stdin().read_to_string(&mut text),
+        _ => std::fs::File::open(path).and_then(|mut file| file.read_to_string(&mut text)),
+    };
+    result.map_err(|e| format!("mmv: Not able to read mapping {}: {}", path.to_string_lossy(), e))?;
+    mapping::parse_mapping(&text, format)
+}
+
+/// Returns the options of a run with explicit destinations, from a mapping, an editor or
+/// interactive edits.
+fn explicit_options(args: &Args, on_conflict: OnConflict) -> Result<MoveOptions, String> {
+    Ok(MoveOptions {
+        on_conflict,
+        mode: args.mode.parse::<Mode>()?,
+        portable: args.portable,
+        ..MoveOptions::default()
+    })
+}
+
+/// Builds the job of a pattern-based run from the command-line arguments.
+fn pattern_job(args: &Args, on_conflict: OnConflict) -> Result<MassMove, String> {
+    let source_pattern = args.source_pattern.as_deref().unwrap_or_default();
+    let target_pattern = args.target_pattern.as_deref().unwrap_or_default();
+    let mut job = MassMove::new(source_pattern, target_pattern)
+        .on_conflict(on_conflict)
+        .mode(args.mode.parse::<Mode>()?)
+        .dry_run(args.dry_run)
+        .portable(args.portable)
+        .exif_fallback(args.exif_fallback.parse::<ExifFallback>()?)
+        .translit_table(args.translit_table.parse::<TranslitTable>()?)
+        .map_missing(args.map_missing.parse::<MissingKey>()?);
+    for spec in &args.name_filter {
+        job = job.name_filter(spec);
+    }
+    for spec in &args.map {
+        job = job.map(spec);
+    }
+    Ok(job)
 }
 
 /// The entry point of the 'mmv' tool. Parses command-line arguments and invokes the file
 /// renaming operation.
 fn main() {
     let args = Args::parse();
-    let result = mass_move::mass_move(&args.source_pattern, &args.target_pattern, args.force);
+    let dry_run = args.dry_run || args.plan_out.is_some();
+    let mut reporter: Box<dyn Reporter> = match (args.json, args.print0) {
+        (true, _) => Box::new(RecordReporter::new(RecordFormat::Json, dry_run)),
+        (_, true) => Box::new(RecordReporter::new(RecordFormat::Nul, dry_run)),
+        _ => {
+            let format = args.quoting_style.parse::<QuotingStyle>().and_then(|quoting| match &args.format {
+                Some(format) => ReportFormat::parse(format, quoting),
+                None => ReportFormat::parse("{src} -> {dst}", quoting),
+            });
+            match format {
+                Ok(format) => Box::new(PrintReporter::new(dry_run).with_format(format)),
+                Err(e) => {
+                    eprint!("{}", e);
+                    std::process::exit(1)
+                }
+            }
+        }
+    };
+    let result = (|| {
+        let on_conflict = match args.force {
+            true => OnConflict::Overwrite,
+            false => args.on_conflict.parse::<OnConflict>()?,
+        };
+        let plan = if let Some(plan_file) = &args.apply {
+            let mut plan = move_plan::load_plan(plan_file)?;
+            plan.revalidate(&explicit_options(&args, on_conflict)?)?;
+            plan
+        } else if let Some(mapping_file) = &args.mapping {
+            let pairs = read_mapping(mapping_file, args.mapping_format.as_deref())?;
+            move_plan::plan_mapping(&pairs, &explicit_options(&args, on_conflict)?)?
+        } else if args.edit {
+            let source_pattern = args.source_pattern.as_deref().unwrap_or_default();
+            edit::plan_edit(source_pattern, &explicit_options(&args, on_conflict)?, edit::run_editor)?
+        } else {
+            pattern_job(&args, on_conflict)?.plan()?
+        };
+        let plan = match args.interactive {
+            true if args.mapping.as_ref().is_some_and(|path| path.to_str() == Some("-")) =>
+                return Err("mmv: Not able to ask interactively when --mapping is read from stdin".to_string()),
+            true => interactive::review(&plan, &explicit_options(&args, on_conflict)?,
+                                        interactive::ask_terminal)?,
+            false => plan,
+        };
+        if let Some(plan_file) = &args.plan_out {
+            move_plan::preview(&plan, reporter.as_mut())?;
+            return move_plan::save_plan(&plan, plan_file);
+        }
+        match args.dry_run {
+            true => move_plan::preview(&plan, reporter.as_mut()),
+            false => move_plan::execute(&plan, reporter.as_mut()),
+        }
+    })();
     match result {
         Ok(_) => std::process::exit(0),
         Err(e) => {
+            reporter.error(&e);
             eprint!("{}", e);
             std::process::exit(1)
         }
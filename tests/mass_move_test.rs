@@ -3,7 +3,13 @@ use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
 use mmove::mass_move::mass_move;
+use mmove::mass_move::{mass_move_with_options, MoveOptions};
+use mmove::reporter::SilentReporter;
+use mmove::template::Filter;
+use mmove::tokens::Tokens;
+use mmove::value_map::MapFilter;
 
+#[allow(clippy::too_many_arguments, clippy::single_match, clippy::useless_format, clippy::manual_flatten)]
 fn test_mmv_with_existing_files(temp_dir: TempDir, path_s: &str, path_d: &str, source_pattern: &str,
                                 dest_pattern: &str, file_paths_source: Vec<&str>,
                                 file_paths_dest: Vec<&str>, force: bool) -> Result<(), String> {
@@ -63,6 +69,7 @@ fn test_mmv_with_existing_files(temp_dir: TempDir, path_s: &str, path_d: &str, s
 }
 
 #[test]
+#[allow(clippy::nonminimal_bool)]
 fn test_mmv_with_different_paths() {
     let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
     let path_s = "path/to/";
@@ -86,6 +93,7 @@ fn test_mmv_with_different_paths() {
 
 
 #[test]
+#[allow(clippy::nonminimal_bool)]
 fn test_mmv_with_same_paths() {
     let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
     let path_s = "path/to/";
@@ -171,6 +179,7 @@ fn test_mmv_with_existent_files() {
 }
 
 #[test]
+#[allow(clippy::nonminimal_bool)]
 fn test_mmv_with_existent_files_force() {
     let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
     let path_s = "path/to/";
@@ -202,3 +211,286 @@ fn test_mmv_with_existent_files_force() {
                                           dest_pattern, file_paths_source, file_paths_dest, true).is_err());
 
 }
+
+#[test]
+fn test_mmv_with_invalid_destination_marker() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_s = "path/to/";
+    let path_d = "path2/to/";
+    let source_pattern = "some_*_filename.*";
+    let dest_pattern = "change_#1_filename.#3";
+    let file_paths_source = vec![
+        "some_A_filename.bin",
+        "some_B_filename.txt",
+    ];
+    let full_path_destination = temp_dir.path().join(path_d).join(dest_pattern);
+
+    let result = test_mmv_with_existing_files(temp_dir, path_s, path_d, source_pattern,
+                                              dest_pattern, file_paths_source, vec![], false);
+
+    let destination = full_path_destination.to_string_lossy();
+    assert_eq!(format!("mmv: Invalid destination pattern: there is no capture #3, \
+                        the source pattern has 2 wildcard(s)\n    {}\n    {}^",
+                       destination, " ".repeat(destination.len() - 2)),
+               result.unwrap_err());
+}
+
+#[test]
+fn test_mmv_with_markers_in_directories() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("path/to");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    for file_path in ["a.jpg", "b.jpg", "c.png"] {
+        File::create(path_source.join(file_path)).expect("Failed to create file");
+    }
+    let destination = temp_dir.path().join("sorted/#2/#1.#2");
+
+    mass_move(&path_source.join("*.*").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    for file_path in ["sorted/jpg/a.jpg", "sorted/jpg/b.jpg", "sorted/png/c.png"] {
+        assert!(temp_dir.path().join(file_path).exists());
+    }
+    assert!(!temp_dir.path().join("sorted/#2").exists());
+}
+
+#[test]
+fn test_mmv_with_metadata_tokens() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("path/to");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("small.txt"), "hello").expect("Failed to write file");
+    fs::write(path_source.join("large.txt"), vec![b'x'; 1536]).expect("Failed to write file");
+    let destination = temp_dir.path().join("by_size/{size:human}/#1.txt");
+
+    mass_move(&path_source.join("*.txt").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    assert!(temp_dir.path().join("by_size/5/small.txt").exists());
+    assert!(temp_dir.path().join("by_size/1.5K/large.txt").exists());
+}
+
+#[test]
+fn test_mmv_with_content_hash_tokens() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("blobs");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("first.bin"), "hello").expect("Failed to write file");
+    fs::write(path_source.join("second.bin"), "world").expect("Failed to write file");
+    let destination = temp_dir.path().join("store/{sha256:12}.#2");
+
+    mass_move(&path_source.join("*.*").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    assert!(temp_dir.path().join("store/2cf24dba5fb0.bin").exists());
+    assert!(temp_dir.path().join("store/486ea46224d1.bin").exists());
+}
+
+#[test]
+fn test_mmv_with_recursive_pattern_and_path_tokens() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("photos");
+    fs::create_dir_all(path_source.join("2023/trip")).expect("Failed to create parent directories");
+    fs::write(path_source.join("top.jpg"), "").expect("Failed to write file");
+    fs::write(path_source.join("2023/trip/a.jpg"), "").expect("Failed to write file");
+    fs::write(path_source.join("2023/trip/notes.txt"), "").expect("Failed to write file");
+    let destination = temp_dir.path().join("flat/{depth}_{relpath:_}#{1}_{parent}.jpg");
+
+    mass_move(&path_source.join("**/*.jpg").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    assert!(temp_dir.path().join("flat/0_top_photos.jpg").exists());
+    assert!(temp_dir.path().join("flat/2_2023_tripa_trip.jpg").exists());
+    assert!(path_source.join("2023/trip/notes.txt").exists());
+}
+
+#[test]
+fn test_mmv_with_named_captures() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("reports");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("2023-11.csv"), "").expect("Failed to write file");
+    let destination = temp_dir.path().join("by_year/#{year}/#{month}#{2}.csv");
+
+    mass_move(&path_source.join("*{year}-*{month}.csv").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    assert!(temp_dir.path().join("by_year/2023/1111.csv").exists());
+}
+
+#[test]
+fn test_mmv_with_arithmetic_and_counter() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("episodes");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("e0.mkv"), "").expect("Failed to write file");
+    fs::write(path_source.join("e9.mkv"), "").expect("Failed to write file");
+    let destination = temp_dir.path().join("renamed/#{n:02}_e#{1+1:03}.mkv");
+
+    mass_move(&path_source.join("e*.mkv").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    assert!(temp_dir.path().join("renamed/01_e001.mkv").exists());
+    assert!(temp_dir.path().join("renamed/02_e010.mkv").exists());
+}
+
+#[test]
+fn test_mmv_with_date_filter() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("reports");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("report_07-11-2023.pdf"), "").expect("Failed to write file");
+    let destination = temp_dir.path().join("#{1|date:%d-%m-%Y:%Y-%m-%d}_report.pdf");
+
+    mass_move(&path_source.join("report_*.pdf").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+    assert!(temp_dir.path().join("2023-11-07_report.pdf").exists());
+
+    fs::write(path_source.join("report_final.pdf"), "").expect("Failed to write file");
+    let result = mass_move(&path_source.join("report_*.pdf").to_string_lossy(),
+                           &destination.to_string_lossy(), false);
+    assert!(result.unwrap_err().contains("'final' is not a date"));
+    assert!(path_source.join("report_final.pdf").exists());
+}
+
+#[test]
+fn test_mmv_with_slug_filters() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("uploads");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("Отчёт за май.PDF"), "").expect("Failed to write file");
+    fs::write(path_source.join("Щука.txt"), "").expect("Failed to write file");
+
+    mass_move(&path_source.join("*.txt").to_string_lossy(),
+              &temp_dir.path().join("#{1|translit:icao}.txt").to_string_lossy(), false)
+        .expect("Failed to move files");
+    assert!(temp_dir.path().join("Shchuka.txt").exists());
+
+    let options = MoveOptions { name_filters: vec![Filter::parse("slug").unwrap()], ..MoveOptions::default() };
+    mass_move_with_options(&path_source.join("*").to_string_lossy(),
+                           &temp_dir.path().join("web/#1").to_string_lossy(), &options,
+                           &Tokens::default(), &mut SilentReporter)
+        .expect("Failed to move files");
+    assert!(temp_dir.path().join("web/otchyot-za-maj.pdf").exists());
+}
+
+#[test]
+fn test_mmv_with_map_filter() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("invoices");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("invoice_17.pdf"), "").expect("Failed to write file");
+    let map_path = temp_dir.path().join("customers.csv");
+    fs::write(&map_path, "17,Acme\n42,Globex\n").expect("Failed to write file");
+    let map_filter = MapFilter::default()
+        .with_map_file(&format!("customers={}", map_path.to_string_lossy()))
+        .expect("Failed to load map");
+    let tokens = Tokens::default().with_filter(Box::new(map_filter));
+
+    mass_move_with_options(&path_source.join("invoice_*.pdf").to_string_lossy(),
+                           &temp_dir.path().join("#{1|map:customers}/#1.pdf").to_string_lossy(),
+                           &MoveOptions::default(), &tokens, &mut SilentReporter)
+        .expect("Failed to move files");
+    assert!(temp_dir.path().join("Acme/17.pdf").exists());
+}
+
+#[test]
+fn test_mmv_sanitizes_destinations() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("in");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("line\nbreak.txt"), "").expect("Failed to write file");
+    fs::write(path_source.join(format!("{}.txt", "a".repeat(200))), "").expect("Failed to write file");
+    let destination = temp_dir.path().join("out/#1#1.txt");
+
+    mass_move(&path_source.join("*.txt").to_string_lossy(),
+              &destination.to_string_lossy(), false).expect("Failed to move files");
+
+    assert!(temp_dir.path().join("out/line_breakline_break.txt").exists());
+    assert!(temp_dir.path().join(format!("out/{}.txt", "a".repeat(251))).exists());
+}
+
+#[test]
+fn test_mmv_reports_all_errors_before_moving() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("in");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    for name in ["report_first.pdf", "report_07-11-2023.pdf", "report_second.pdf"] {
+        fs::write(path_source.join(name), "").expect("Failed to write file");
+    }
+    let destination = temp_dir.path().join("#{1|date:%d-%m-%Y}.pdf");
+
+    let error = mass_move(&path_source.join("report_*.pdf").to_string_lossy(),
+                          &destination.to_string_lossy(), false).unwrap_err();
+
+    assert_eq!(error.lines().count(), 2);
+    assert!(error.contains("'first' is not a date") && error.contains("'second' is not a date"));
+    assert!(path_source.join("report_07-11-2023.pdf").exists());
+}
+
+#[test]
+fn test_mmv_portable_destinations() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("in");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    for name in ["notes.txt", "Notes.txt", "con.txt", "todo.txt"] {
+        fs::write(path_source.join(name), "").expect("Failed to write file");
+    }
+    let source = path_source.join("*.txt");
+    let destination = temp_dir.path().join("out/#1.txt");
+    let options = MoveOptions { portable: true, ..MoveOptions::default() };
+
+    let error = mass_move_with_options(&source.to_string_lossy(), &destination.to_string_lossy(),
+                                       &options, &Tokens::default(), &mut SilentReporter).unwrap_err();
+
+    assert_eq!(error.lines().count(), 2);
+    assert!(error.contains("'con.txt' is a reserved name on Windows"));
+    assert!(error.contains("Notes.txt and ") && error.contains("differ only in case"));
+    assert!(!temp_dir.path().join("out").exists());
+
+    mass_move_with_options(&source.to_string_lossy(), &destination.to_string_lossy(),
+                           &MoveOptions::default(), &Tokens::default(), &mut SilentReporter).unwrap();
+    assert!(temp_dir.path().join("out/con.txt").exists());
+    assert!(temp_dir.path().join("out/Notes.txt").exists());
+
+    // Only the names the pattern produces are checked, not the directory it starts in
+    let fixed = temp_dir.path().join("x:y");
+    fs::create_dir_all(&fixed).expect("Failed to create parent directories");
+    mass_move_with_options(&temp_dir.path().join("out/todo.*").to_string_lossy(),
+                           &fixed.join("#1.md").to_string_lossy(), &options, &Tokens::default(),
+                           &mut SilentReporter).unwrap();
+    assert!(fixed.join("txt.md").exists());
+}
+
+#[test]
+fn test_mmv_force_keeps_files_moved_onto_themselves() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("in");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("photo.jpg"), "keep me").expect("Failed to write file");
+    fs::write(path_source.join("scan.jpg"), "rename me").expect("Failed to write file");
+
+    mass_move(&path_source.join("*.jpg").to_string_lossy(),
+              &path_source.join("#1.jpg").to_string_lossy(), true).expect("Failed to move files");
+    assert_eq!(fs::read_to_string(path_source.join("photo.jpg")).unwrap(), "keep me");
+    assert_eq!(fs::read_to_string(path_source.join("scan.jpg")).unwrap(), "rename me");
+}
+
+#[test]
+fn test_mmv_braces_start_tokens() {
+    let temp_dir = TempDir::new("my_temp_dir").expect("Failed to create temporary directory");
+    let path_source = temp_dir.path().join("in");
+    fs::create_dir_all(&path_source).expect("Failed to create parent directories");
+    fs::write(path_source.join("a.txt"), "").expect("Failed to write file");
+    let source = path_source.join("*.txt");
+
+    // A bare `{` starts a token, unlike in earlier versions, so unknown names fail up front
+    let error = mass_move(&source.to_string_lossy(),
+                          &temp_dir.path().join("backup/{old}/#1.txt").to_string_lossy(), false).unwrap_err();
+    assert!(error.contains("unknown token 'old'"));
+    assert!(path_source.join("a.txt").exists());
+
+    mass_move(&source.to_string_lossy(), &temp_dir.path().join("backup/{{old}}/#1.txt").to_string_lossy(),
+              false).expect("Failed to move files");
+    assert!(temp_dir.path().join("backup/{old}/a.txt").exists());
+}